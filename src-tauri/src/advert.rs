@@ -0,0 +1,110 @@
+use crate::p2p::ShareData;
+
+/// Bumped whenever the friend exchange wire format changes
+pub const PROTOCOL_VERSION: u8 = 1;
+pub const APP_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// mDNS user data is limited to 245 bytes
+const MAX_USER_DATA_LEN: usize = 245;
+const ADVERT_TAG: &str = "vf";
+
+// ============================================================================
+// mDNS Advertisement
+// ============================================================================
+
+/// What we announce about ourselves on the local network.
+///
+/// Encoded as `vf|<protocol>|<app version>|<semester>|<profile hash>|<name>`;
+/// the name goes last so it may contain `|` and is the part that gets cut.
+/// Peers running older builds publish a bare username, which parses as
+/// protocol 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Advertisement {
+    pub name: String,
+    pub semester: Option<u32>,
+    pub app_version: Option<String>,
+    pub protocol: u8,
+    pub profile_hash: Option<String>,
+}
+
+impl Advertisement {
+    pub fn from_share_data(share_data: &ShareData) -> Self {
+        Self {
+            name: share_data.u.clone(),
+            semester: Some(share_data.s),
+            app_version: Some(APP_VERSION.to_string()),
+            protocol: PROTOCOL_VERSION,
+            profile_hash: Some(profile_hash(share_data)),
+        }
+    }
+
+    pub fn is_compatible(&self) -> bool {
+        self.protocol == PROTOCOL_VERSION
+    }
+
+    /// Encode, truncating the name so the whole record fits in mDNS user data
+    pub fn encode(&self) -> String {
+        let header = format!(
+            "{}|{}|{}|{}|{}|",
+            ADVERT_TAG,
+            self.protocol,
+            self.app_version.as_deref().unwrap_or(""),
+            self.semester.map(|s| s.to_string()).unwrap_or_default(),
+            self.profile_hash.as_deref().unwrap_or(""),
+        );
+        let budget = MAX_USER_DATA_LEN.saturating_sub(header.len());
+        format!("{}{}", header, truncate_utf8(&self.name, budget))
+    }
+
+    pub fn parse(raw: &str) -> Self {
+        let parts: Vec<&str> = raw.splitn(6, '|').collect();
+        if parts.len() != 6 || parts[0] != ADVERT_TAG {
+            return Self::legacy(raw);
+        }
+        let Ok(protocol) = parts[1].parse::<u8>() else {
+            return Self::legacy(raw);
+        };
+
+        let non_empty = |s: &str| (!s.is_empty()).then(|| s.to_string());
+        Self {
+            name: parts[5].to_string(),
+            semester: parts[3].parse().ok(),
+            app_version: non_empty(parts[2]),
+            protocol,
+            profile_hash: non_empty(parts[4]),
+        }
+    }
+
+    fn legacy(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            semester: None,
+            app_version: None,
+            protocol: 0,
+            profile_hash: None,
+        }
+    }
+}
+
+/// Short, stable fingerprint of a profile (FNV-1a over its JSON form), so
+/// peers can tell whether the share data they hold is stale
+pub fn profile_hash(share_data: &ShareData) -> String {
+    let bytes = serde_json::to_vec(share_data).unwrap_or_default();
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in bytes {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    format!("{:08x}", hash)
+}
+
+fn truncate_utf8(s: &str, max_len: usize) -> &str {
+    if s.len() <= max_len {
+        return s;
+    }
+    let mut end = max_len;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
@@ -0,0 +1,505 @@
+//! Whole-profile backup for moving VFriend to another device.
+//!
+//! The archive is JSON. Without a password it is a plain `Backup`; with one,
+//! the same JSON is sealed with ChaCha20-Poly1305 under a PBKDF2-SHA256 key
+//! and wrapped in `EncryptedBackup`. Both carry `format` and `version` so a
+//! later app can tell what it is reading.
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::DialogExt;
+use tauri_plugin_fs::{FilePath, FsExt, OpenOptions};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::oneshot;
+
+use crate::p2p::unix_timestamp;
+use crate::p2p_commands::{identity_path, write_identity, ServiceState};
+use crate::registry::FriendRecord;
+
+const BACKUP_FORMAT: &str = "vfriend-backup";
+const BACKUP_VERSION: u32 = 1;
+const PBKDF2_ROUNDS: u32 = 210_000;
+
+/// Store files and keys the frontend keeps its data under
+const USER_STORE: &str = "user.json";
+const FRIENDS_STORE: &str = "friends.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Backup {
+    format: String,
+    version: u32,
+    created_at: u64,
+    /// The frontend's `userData`: profile, timetable and settings
+    user: Option<Value>,
+    /// The frontend's `friends` list, each with its `ShareData` fields
+    friends: Vec<Value>,
+    /// The friend service's records, which tie friends to endpoint IDs
+    #[serde(default)]
+    registry: Vec<FriendRecord>,
+    /// Raw endpoint identity, base64; only when asked for
+    #[serde(default)]
+    identity_key: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedBackup {
+    format: String,
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Keep what is here and add or refresh friends from the backup
+    #[default]
+    Merge,
+    /// Make this device match the backup
+    Replace,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    pub profile_restored: bool,
+    pub friends_added: usize,
+    pub friends_updated: usize,
+    pub endpoints_restored: usize,
+    /// Endpoint records left out because the friend service, which is the
+    /// only place they live, was not running; import again once it is
+    pub endpoints_skipped: usize,
+    pub identity_restored: bool,
+}
+
+fn cipher_for(password: &str, salt: &[u8]) -> ChaCha20Poly1305 {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<sha2::Sha256>(password.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+fn seal(json: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    let salt = rand::random::<[u8; 16]>();
+    let nonce = rand::random::<[u8; 12]>();
+    let ciphertext = cipher_for(password, &salt)
+        .encrypt(Nonce::from_slice(&nonce), json)
+        .map_err(|e| format!("Failed to encrypt backup: {}", e))?;
+
+    serde_json::to_vec_pretty(&EncryptedBackup {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ciphertext: STANDARD.encode(ciphertext),
+    })
+    .map_err(|e| format!("Failed to serialize backup: {}", e))
+}
+
+fn unseal(sealed: EncryptedBackup, password: Option<&str>) -> Result<Vec<u8>, String> {
+    let password = password.ok_or("This backup is password protected")?;
+    let decode = |field: &str, value: &str| {
+        STANDARD
+            .decode(value)
+            .map_err(|e| format!("Backup {} is not valid base64: {}", field, e))
+    };
+    let salt = decode("salt", &sealed.salt)?;
+    let nonce = decode("nonce", &sealed.nonce)?;
+    let ciphertext = decode("ciphertext", &sealed.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err("Backup nonce has the wrong length".to_string());
+    }
+
+    cipher_for(password, &salt)
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| "Wrong password or damaged backup".to_string())
+}
+
+/// Parse an archive, decrypting it first if needed
+fn read_backup(bytes: &[u8], password: Option<&str>) -> Result<Backup, String> {
+    let value: Value =
+        serde_json::from_slice(bytes).map_err(|e| format!("Not a backup file: {}", e))?;
+    if value.get("format").and_then(Value::as_str) != Some(BACKUP_FORMAT) {
+        return Err("Not a VFriend backup".to_string());
+    }
+    let version = value.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version != BACKUP_VERSION as u64 {
+        return Err(format!(
+            "Backup version {} is not supported; update the app",
+            version
+        ));
+    }
+
+    if value.get("ciphertext").is_some() {
+        let sealed: EncryptedBackup = serde_json::from_value(value)
+            .map_err(|e| format!("Failed to parse encrypted backup: {}", e))?;
+        let json = unseal(sealed, password)?;
+        return serde_json::from_slice(&json)
+            .map_err(|e| format!("Failed to parse decrypted backup: {}", e));
+    }
+    serde_json::from_value(value).map_err(|e| format!("Failed to parse backup: {}", e))
+}
+
+fn collect_backup(
+    app: &AppHandle,
+    registry: Vec<FriendRecord>,
+    include_identity: bool,
+) -> Result<Backup, String> {
+    let user_store = app
+        .store(USER_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", USER_STORE, e))?;
+    let friends_store = app
+        .store(FRIENDS_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", FRIENDS_STORE, e))?;
+
+    let friends = match friends_store.get("friends") {
+        Some(Value::Array(friends)) => friends,
+        _ => Vec::new(),
+    };
+    let identity_key = if include_identity {
+        let path = identity_path(app)?;
+        match std::fs::read(&path) {
+            Ok(bytes) => Some(STANDARD.encode(bytes)),
+            Err(_) => None, // the friend service was never started here
+        }
+    } else {
+        None
+    };
+
+    Ok(Backup {
+        format: BACKUP_FORMAT.to_string(),
+        version: BACKUP_VERSION,
+        created_at: unix_timestamp(),
+        user: user_store.get("userData").filter(|user| !user.is_null()),
+        friends,
+        registry,
+        identity_key,
+    })
+}
+
+fn registration(friend: &Value) -> Option<&str> {
+    friend.get("r").and_then(Value::as_str)
+}
+
+fn timestamp(friend: &Value) -> &str {
+    friend.get("t").and_then(Value::as_str).unwrap_or("")
+}
+
+/// Fold the backup's friends into `friends`, matched by registration number;
+/// a friend already here is only replaced by a newer copy
+fn merge_friends(
+    mut friends: Vec<Value>,
+    incoming: Vec<Value>,
+    report: &mut ImportReport,
+) -> Vec<Value> {
+    for incoming in incoming {
+        let Some(reg) = registration(&incoming) else {
+            continue;
+        };
+        match friends.iter_mut().find(|f| registration(f) == Some(reg)) {
+            Some(existing) => {
+                // ISO timestamps compare correctly as strings
+                if timestamp(&incoming) > timestamp(existing) {
+                    *existing = incoming;
+                    report.friends_updated += 1;
+                }
+            }
+            None => {
+                friends.push(incoming);
+                report.friends_added += 1;
+            }
+        }
+    }
+    friends
+}
+
+fn apply_backup(
+    app: &AppHandle,
+    backup: &mut Backup,
+    mode: ImportMode,
+) -> Result<ImportReport, String> {
+    let user_store = app
+        .store(USER_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", USER_STORE, e))?;
+    let friends_store = app
+        .store(FRIENDS_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", FRIENDS_STORE, e))?;
+    let mut report = ImportReport::default();
+
+    let has_profile = matches!(user_store.get("userData"), Some(user) if !user.is_null());
+    if let Some(user) = backup.user.take() {
+        if matches!(mode, ImportMode::Replace) || !has_profile {
+            user_store.set("userData", user);
+            report.profile_restored = true;
+        }
+    }
+
+    let friends = match (mode, friends_store.get("friends")) {
+        (ImportMode::Merge, Some(Value::Array(friends))) => friends,
+        _ => Vec::new(),
+    };
+    let friends = merge_friends(friends, std::mem::take(&mut backup.friends), &mut report);
+    friends_store.set("friends", Value::Array(friends));
+
+    user_store
+        .save()
+        .map_err(|e| format!("Failed to save {}: {}", USER_STORE, e))?;
+    friends_store
+        .save()
+        .map_err(|e| format!("Failed to save {}: {}", FRIENDS_STORE, e))?;
+
+    if let Some(key) = backup.identity_key.take() {
+        let path = identity_path(app)?;
+        if matches!(mode, ImportMode::Replace) || !path.exists() {
+            let bytes = STANDARD
+                .decode(key)
+                .map_err(|e| format!("Backup identity is not valid base64: {}", e))?;
+            let bytes: [u8; 32] = bytes
+                .try_into()
+                .map_err(|_| "Backup identity is not a 32 byte key".to_string())?;
+            write_identity(&path, &bytes)?;
+            report.identity_restored = true;
+        }
+    }
+    Ok(report)
+}
+
+/// Hand the backup's endpoint records to the running friend service, so
+/// forwarded updates and reconnects find friends again. Returns how many
+/// records were added, or `None` if the service is not running.
+async fn restore_registry(
+    app: &AppHandle,
+    backup: &[FriendRecord],
+    mode: ImportMode,
+) -> Option<usize> {
+    let state = app.state::<ServiceState>().inner().clone();
+    let service = state.lock().await.running()?;
+    let mut records = match mode {
+        ImportMode::Merge => service.known_friends().await,
+        ImportMode::Replace => Vec::new(),
+    };
+    let mut restored = 0;
+    for record in backup {
+        // Records already here are newer than the backup's
+        if !records.iter().any(|r| r.endpoint_id == record.endpoint_id) {
+            records.push(record.clone());
+            restored += 1;
+        }
+    }
+    service.set_known_friends(records).await;
+    Some(restored)
+}
+
+async fn known_endpoints(app: &AppHandle) -> Vec<FriendRecord> {
+    let state = app.state::<ServiceState>().inner().clone();
+    let service = state.lock().await.running();
+    match service {
+        Some(service) => service.known_friends().await,
+        None => Vec::new(),
+    }
+}
+
+/// Wait for a callback-style file dialog without blocking a runtime thread;
+/// `None` when it was cancelled
+async fn ask_path(show: impl FnOnce(oneshot::Sender<Option<FilePath>>)) -> Option<FilePath> {
+    let (tx, rx) = oneshot::channel();
+    show(tx);
+    rx.await.ok().flatten()
+}
+
+fn write_file(app: &AppHandle, path: FilePath, bytes: &[u8]) -> Result<(), String> {
+    use std::io::Write;
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    let mut file = app
+        .fs()
+        .open(path, options)
+        .map_err(|e| format!("Failed to open backup file: {}", e))?;
+    file.write_all(bytes)
+        .map_err(|e| format!("Failed to write backup: {}", e))
+}
+
+/// Ask where to save, then write the archive. Returns the chosen path, or
+/// `None` if the dialog was cancelled.
+#[tauri::command]
+pub async fn export_backup(
+    app_handle: AppHandle,
+    password: Option<String>,
+    include_identity: bool,
+) -> Result<Option<String>, String> {
+    let registry = known_endpoints(&app_handle).await;
+    let backup = collect_backup(&app_handle, registry, include_identity)?;
+    let json = serde_json::to_vec_pretty(&backup)
+        .map_err(|e| format!("Failed to serialize backup: {}", e))?;
+    let bytes = match password.as_deref().filter(|p| !p.is_empty()) {
+        Some(password) => seal(&json, password)?,
+        None => json,
+    };
+
+    let dialog = app_handle
+        .dialog()
+        .file()
+        .add_filter("VFriend backup", &["vfbackup", "json"])
+        .set_file_name("vfriend.vfbackup");
+    let Some(path) = ask_path(|tx| {
+        dialog.save_file(move |path| {
+            let _ = tx.send(path);
+        })
+    })
+    .await
+    else {
+        return Ok(None);
+    };
+    let shown = path.to_string();
+    write_file(&app_handle, path, &bytes)?;
+    Ok(Some(shown))
+}
+
+/// Ask for an archive and restore it. If the identity was restored, call
+/// `init_friend_service` again so the endpoint picks it up; the restored
+/// endpoint records carry over. Endpoint records only reach a running
+/// friend service; `endpoints_skipped` counts the ones that did not.
+#[tauri::command]
+pub async fn import_backup(
+    app_handle: AppHandle,
+    password: Option<String>,
+    mode: Option<ImportMode>,
+) -> Result<Option<ImportReport>, String> {
+    let dialog = app_handle
+        .dialog()
+        .file()
+        .add_filter("VFriend backup", &["vfbackup", "json"]);
+    let Some(path) = ask_path(|tx| {
+        dialog.pick_file(move |path| {
+            let _ = tx.send(path);
+        })
+    })
+    .await
+    else {
+        return Ok(None);
+    };
+    let bytes = app_handle
+        .fs()
+        .read(path)
+        .map_err(|e| format!("Failed to read backup file: {}", e))?;
+
+    let mut backup = read_backup(&bytes, password.as_deref())?;
+    let mode = mode.unwrap_or_default();
+    let mut report = apply_backup(&app_handle, &mut backup, mode)?;
+    match restore_registry(&app_handle, &backup.registry, mode).await {
+        Some(restored) => report.endpoints_restored = restored,
+        None => report.endpoints_skipped = backup.registry.len(),
+    }
+    Ok(Some(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn backup_json() -> Vec<u8> {
+        serde_json::to_vec(&json!({
+            "format": BACKUP_FORMAT,
+            "version": BACKUP_VERSION,
+            "created_at": 1_700_000_000,
+            "user": { "u": "Asha" },
+            "friends": [{ "r": "23BCE0001", "t": "2025-01-01T00:00:00Z" }],
+        }))
+        .unwrap()
+    }
+
+    fn friend(reg: &str, timestamp: &str, name: &str) -> Value {
+        json!({ "r": reg, "t": timestamp, "u": name })
+    }
+
+    #[test]
+    fn sealed_backup_round_trips() {
+        let sealed = seal(&backup_json(), "hunter2").unwrap();
+        let backup = read_backup(&sealed, Some("hunter2")).unwrap();
+        assert_eq!(backup.user, Some(json!({ "u": "Asha" })));
+        assert_eq!(backup.friends.len(), 1);
+
+        let plain = read_backup(&backup_json(), None).unwrap();
+        assert_eq!(plain.friends, backup.friends);
+    }
+
+    #[test]
+    fn wrong_or_missing_password_is_refused() {
+        let sealed = seal(&backup_json(), "hunter2").unwrap();
+        assert_eq!(
+            read_backup(&sealed, Some("hunter3")).unwrap_err(),
+            "Wrong password or damaged backup"
+        );
+        assert!(read_backup(&sealed, None).is_err());
+    }
+
+    #[test]
+    fn tampered_backup_is_refused() {
+        let sealed: Value =
+            serde_json::from_slice(&seal(&backup_json(), "hunter2").unwrap()).unwrap();
+        let flip_first_byte = |field: &str| {
+            let mut bytes = STANDARD.decode(sealed[field].as_str().unwrap()).unwrap();
+            bytes[0] ^= 1;
+            let mut tampered = sealed.clone();
+            tampered[field] = json!(STANDARD.encode(bytes));
+            serde_json::to_vec(&tampered).unwrap()
+        };
+
+        // The salt and nonce are not authenticated on their own, but a
+        // change to either gives a key or keystream the tag will not match
+        for field in ["ciphertext", "salt", "nonce"] {
+            assert_eq!(
+                read_backup(&flip_first_byte(field), Some("hunter2")).unwrap_err(),
+                "Wrong password or damaged backup",
+                "{}",
+                field
+            );
+        }
+
+        let mut tampered = sealed.clone();
+        tampered["format"] = json!("something-else");
+        let bytes = serde_json::to_vec(&tampered).unwrap();
+        assert_eq!(
+            read_backup(&bytes, Some("hunter2")).unwrap_err(),
+            "Not a VFriend backup"
+        );
+
+        let mut tampered = sealed;
+        tampered["version"] = json!(BACKUP_VERSION + 1);
+        let bytes = serde_json::to_vec(&tampered).unwrap();
+        assert!(read_backup(&bytes, Some("hunter2")).is_err());
+    }
+
+    #[test]
+    fn merge_keeps_local_friends() {
+        let local = vec![
+            friend("23BCE0001", "2025-03-01T00:00:00Z", "local newer"),
+            friend("23BCE0002", "2025-01-01T00:00:00Z", "local older"),
+            friend("23BCE0003", "2025-01-01T00:00:00Z", "only here"),
+        ];
+        let incoming = vec![
+            friend("23BCE0001", "2025-02-01T00:00:00Z", "backup older"),
+            friend("23BCE0002", "2025-02-01T00:00:00Z", "backup newer"),
+            friend("23BCE0004", "2025-02-01T00:00:00Z", "only in backup"),
+            json!({ "u": "no registration" }),
+        ];
+
+        let mut report = ImportReport::default();
+        let merged = merge_friends(local, incoming, &mut report);
+
+        let names: Vec<_> = merged.iter().map(|f| f["u"].as_str().unwrap()).collect();
+        assert_eq!(
+            names,
+            ["local newer", "backup newer", "only here", "only in backup"]
+        );
+        assert_eq!(report.friends_added, 1);
+        assert_eq!(report.friends_updated, 1);
+    }
+}
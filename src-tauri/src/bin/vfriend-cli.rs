@@ -0,0 +1,273 @@
+//! Headless access to the P2P friend exchange, for debugging pairing and
+//! scripting tests without the GUI.
+//!
+//! ```text
+//! vfriend-cli id        [--key FILE]
+//! vfriend-cli ticket    --share FILE [--key FILE]
+//! vfriend-cli advertise --share FILE [--key FILE] [--accept | --reject] [--out DIR]
+//! vfriend-cli peers     [--key FILE] [--secs N]
+//! vfriend-cli send      PEER_ID_OR_TICKET --share FILE [--key FILE] [--out DIR]
+//! ```
+
+use iroh::SecretKey;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::mpsc;
+use vitfriend_lib::model::migrate_share_data;
+use vitfriend_lib::p2p::{FriendEvent, FriendExchangeService, ShareData};
+
+const USAGE: &str = "usage: vfriend-cli <id|ticket|advertise|peers|send> [options]
+
+  id                          print this endpoint's ID
+  ticket     --share FILE     print a connection ticket, then accept whoever uses it
+  advertise  --share FILE     advertise over mDNS and answer incoming requests
+             [--accept | --reject]   answer without asking
+  peers      [--secs N]       list peers discovered within N seconds (default 10)
+  send       PEER --share FILE       send a friend request to an endpoint ID or ticket
+
+common options:
+  --key FILE   persist the endpoint identity in FILE (created if missing)
+  --out DIR    also write received share data to DIR/<registration>.json";
+
+#[derive(Debug, Default)]
+struct Args {
+    command: String,
+    target: Option<String>,
+    key: Option<PathBuf>,
+    share: Option<PathBuf>,
+    out: Option<PathBuf>,
+    secs: u64,
+    policy: Policy,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum Policy {
+    #[default]
+    Ask,
+    Accept,
+    Reject,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut raw = std::env::args().skip(1);
+    let mut args = Args {
+        command: raw.next().ok_or(USAGE)?,
+        secs: 10,
+        ..Args::default()
+    };
+
+    while let Some(arg) = raw.next() {
+        let mut value = |name: &str| raw.next().ok_or(format!("{} needs a value", name));
+        match arg.as_str() {
+            "--key" => args.key = Some(value("--key")?.into()),
+            "--share" => args.share = Some(value("--share")?.into()),
+            "--out" => args.out = Some(value("--out")?.into()),
+            "--secs" => {
+                args.secs = value("--secs")?
+                    .parse()
+                    .map_err(|e| format!("Invalid --secs: {}", e))?
+            }
+            "--accept" => args.policy = Policy::Accept,
+            "--reject" => args.policy = Policy::Reject,
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            other if other.starts_with("--") => return Err(format!("Unknown option {}", other)),
+            other => args.target = Some(other.to_string()),
+        }
+    }
+    Ok(args)
+}
+
+/// Load the identity from `path`, generating and saving one on first use
+fn load_or_create_key(path: &Path) -> Result<SecretKey, String> {
+    if path.exists() {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read key: {}", e))?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| format!("{} is not a 32 byte key", path.display()))?;
+        return Ok(SecretKey::from_bytes(&bytes));
+    }
+
+    let bytes = rand::random::<[u8; 32]>();
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // Only the owner may read the key
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(&bytes))
+        .map_err(|e| format!("Failed to write key: {}", e))?;
+    Ok(SecretKey::from_bytes(&bytes))
+}
+
+fn load_share_data(args: &Args) -> Result<ShareData, String> {
+    let path = args.share.as_ref().ok_or("--share FILE is required")?;
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let value =
+        serde_json::from_str(&json).map_err(|e| format!("Failed to parse share data: {}", e))?;
+    migrate_share_data(value)
+}
+
+/// Print received share data and optionally save it under `--out`
+fn dump_share_data(args: &Args, share_data: &ShareData) -> Result<(), String> {
+    let json = serde_json::to_string_pretty(share_data)
+        .map_err(|e| format!("Failed to serialize share data: {}", e))?;
+    println!("{}", json);
+
+    if let Some(dir) = &args.out {
+        let path = dir.join(format!("{}.json", share_data.r));
+        std::fs::write(&path, json)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        eprintln!("saved {}", path.display());
+    }
+    Ok(())
+}
+
+async fn start_service(
+    args: &Args,
+) -> Result<(FriendExchangeService, mpsc::UnboundedReceiver<FriendEvent>), String> {
+    let service = match &args.key {
+        Some(path) => FriendExchangeService::with_secret_key(load_or_create_key(path)?).await?,
+        None => FriendExchangeService::new().await?,
+    };
+    let (tx, rx) = mpsc::unbounded_channel();
+    service.start(tx).await?;
+    Ok((service, rx))
+}
+
+async fn ask(question: &str) -> bool {
+    eprint!("{} [y/N] ", question);
+    let mut line = String::new();
+    let mut stdin = BufReader::new(tokio::io::stdin());
+    stdin.read_line(&mut line).await.is_ok() && line.trim().eq_ignore_ascii_case("y")
+}
+
+/// Print events until Ctrl-C, answering incoming requests per `args.policy`
+async fn serve(
+    args: &Args,
+    service: &FriendExchangeService,
+    mut rx: mpsc::UnboundedReceiver<FriendEvent>,
+    my_share_data: ShareData,
+) -> Result<(), String> {
+    loop {
+        let event = tokio::select! {
+            event = rx.recv() => match event {
+                Some(event) => event,
+                None => return Ok(()),
+            },
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+
+        match event {
+            FriendEvent::IncomingRequest { request } => {
+                eprintln!(
+                    "request from {} ({}) via {}",
+                    request.name, request.from, request.remote_id
+                );
+                let accept = match args.policy {
+                    Policy::Accept => true,
+                    Policy::Reject => false,
+                    Policy::Ask => ask("accept?").await,
+                };
+                if accept {
+                    // Their data arrives as a RequestAccepted event
+                    service
+                        .accept_friend_request(request.remote_id, my_share_data.clone())
+                        .await?;
+                } else {
+                    service.reject_friend_request(request.remote_id).await?;
+                    eprintln!("rejected");
+                }
+            }
+            FriendEvent::RequestAccepted { share_data }
+            | FriendEvent::DataReceived { share_data } => dump_share_data(args, &share_data)?,
+            other => eprintln!("{:?}", other),
+        }
+    }
+}
+
+async fn run(args: Args) -> Result<(), String> {
+    match args.command.as_str() {
+        "id" => {
+            let (service, _rx) = start_service(&args).await?;
+            println!("{}", service.get_endpoint_id());
+            service.shutdown().await
+        }
+        "ticket" => {
+            let my_share_data = load_share_data(&args)?;
+            let (service, rx) = start_service(&args).await?;
+            service.set_share_data(my_share_data.clone()).await;
+
+            let link = service.create_ticket().await.to_link()?;
+            println!("{}", link.ticket);
+            eprintln!("{}", link.deep_link);
+
+            serve(&args, &service, rx, my_share_data).await
+        }
+        "advertise" => {
+            let my_share_data = load_share_data(&args)?;
+            let (service, rx) = start_service(&args).await?;
+            service.set_share_data(my_share_data.clone()).await;
+            service.start_discovery().await;
+            eprintln!("advertising as {}", service.get_endpoint_id());
+
+            serve(&args, &service, rx, my_share_data).await
+        }
+        "peers" => {
+            let (service, _rx) = start_service(&args).await?;
+            service.start_discovery().await;
+            tokio::time::sleep(Duration::from_secs(args.secs)).await;
+
+            for peer in service.list_nearby_peers().await {
+                println!(
+                    "{}\t{}\tsem {}\tv{}\t{}{}",
+                    peer.endpoint_id,
+                    peer.name,
+                    peer.semester
+                        .map_or_else(|| "?".to_string(), |s| s.to_string()),
+                    peer.app_version.as_deref().unwrap_or("?"),
+                    if peer.compatible { "" } else { "incompatible " },
+                    if peer.is_friend { "friend" } else { "" },
+                );
+            }
+            service.shutdown().await
+        }
+        "send" => {
+            let target = args
+                .target
+                .clone()
+                .ok_or("send needs a PEER_ID or ticket")?;
+            let my_share_data = load_share_data(&args)?;
+            let (service, _rx) = start_service(&args).await?;
+            service.set_share_data(my_share_data.clone()).await;
+
+            let their_data = if target.contains("vft1") {
+                service
+                    .send_friend_request_with_ticket(&target, my_share_data)
+                    .await?
+            } else {
+                service.send_friend_request(target, my_share_data).await?
+            };
+            dump_share_data(&args, &their_data)?;
+            service.shutdown().await
+        }
+        _ => Err(USAGE.to_string()),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let result = match parse_args() {
+        Ok(args) => run(args).await,
+        Err(message) => Err(message),
+    };
+    if let Err(message) = result {
+        eprintln!("{}", message);
+        std::process::exit(1);
+    }
+}
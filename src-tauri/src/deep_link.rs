@@ -0,0 +1,252 @@
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_deep_link::DeepLinkExt;
+
+use crate::p2p::ShareData;
+use crate::share_codec::decode_share_data;
+use crate::ticket::FriendTicket;
+
+/// Link prefixes we answer to; everything after them is the payload
+const LINK_PREFIXES: [&str; 3] = [
+    "vfriend://",
+    "https://vfriend.preetham.top/",
+    "http://vfriend.preetham.top/",
+];
+
+/// What an opened link turned out to contain, sent to the UI as `link-event`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type")]
+pub enum LinkEvent {
+    /// A share code with someone's timetable
+    FriendLinkReceived {
+        url: String,
+        share_data: ShareData,
+    },
+    /// A P2P connection ticket; pass `ticket` to `send_friend_request_with_ticket`
+    InviteReceived {
+        url: String,
+        ticket: String,
+        endpoint_id: String,
+    },
+    /// An access code from before the binary codec, still decoded in the UI
+    LegacyLinkReceived {
+        url: String,
+        payload: String,
+    },
+    InvalidLink {
+        url: String,
+        error: String,
+    },
+}
+
+/// Links that arrived before the UI was listening (cold start)
+#[derive(Default)]
+pub struct PendingLinks(Mutex<Vec<LinkEvent>>);
+
+fn parse_invite(url: &str, ticket: &str) -> LinkEvent {
+    let checked = FriendTicket::decode(ticket).and_then(|t| {
+        t.endpoint_addr()?;
+        Ok(t.i)
+    });
+    match checked {
+        Ok(endpoint_id) => LinkEvent::InviteReceived {
+            url: url.to_string(),
+            ticket: ticket.to_string(),
+            endpoint_id,
+        },
+        Err(error) => LinkEvent::InvalidLink {
+            url: url.to_string(),
+            error,
+        },
+    }
+}
+
+fn parse_share(url: &str, code: &str) -> LinkEvent {
+    match decode_share_data(code) {
+        Ok(share_data) => LinkEvent::FriendLinkReceived {
+            url: url.to_string(),
+            share_data,
+        },
+        Err(error) => LinkEvent::InvalidLink {
+            url: url.to_string(),
+            error: error.to_string(),
+        },
+    }
+}
+
+/// Classify and validate a `vfriend://` or `https://vfriend.preetham.top/` link
+pub fn parse_link(url: &str) -> LinkEvent {
+    let invalid = |error: &str| LinkEvent::InvalidLink {
+        url: url.to_string(),
+        error: error.to_string(),
+    };
+
+    let trimmed = url.trim();
+    let Some(rest) = LINK_PREFIXES
+        .iter()
+        .find_map(|prefix| trimmed.strip_prefix(prefix))
+    else {
+        return invalid("Not a VFriend link");
+    };
+    let payload = rest.split(['?', '#']).next().unwrap_or_default();
+
+    // Tickets and share codes are URL-safe base64, so a trailing '/' after
+    // them is only the path's. A bare legacy code is standard base64 and
+    // may really end in one.
+    if let Some(ticket) = payload.strip_prefix("connect/") {
+        return parse_invite(url, ticket.trim_end_matches('/'));
+    }
+    if let Some(code) = payload.strip_prefix("share/") {
+        return parse_share(url, code.trim_end_matches('/'));
+    }
+    if payload.is_empty() || payload == "/" {
+        return invalid("Link has nothing in it");
+    }
+    if FriendTicket::decode(payload).is_ok() {
+        return parse_invite(url, payload);
+    }
+    match decode_share_data(payload) {
+        Ok(share_data) => LinkEvent::FriendLinkReceived {
+            url: url.to_string(),
+            share_data,
+        },
+        Err(_) => LinkEvent::LegacyLinkReceived {
+            url: url.to_string(),
+            payload: payload.to_string(),
+        },
+    }
+}
+
+/// A launch link is only queued, since no page is listening yet and one
+/// that is would import it a second time from `take_pending_links`
+fn dispatch(app: &AppHandle, url: &str, cold_start: bool) {
+    let event = parse_link(url);
+    if cold_start {
+        app.state::<PendingLinks>().0.lock().unwrap().push(event);
+    } else {
+        let _ = app.emit("link-event", event);
+    }
+}
+
+/// Handle the link the app was launched with and any opened while running
+pub(crate) fn init(app: &AppHandle) {
+    match app.deep_link().get_current() {
+        Ok(Some(urls)) => {
+            for url in urls {
+                dispatch(app, url.as_str(), true);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("Failed to read launch deep link: {}", e),
+    }
+
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        for url in event.urls() {
+            dispatch(&handle, url.as_str(), false);
+        }
+    });
+}
+
+/// Bring the existing window forward when a second instance is launched;
+/// the deep-link plugin has already forwarded its URL to `on_open_url`
+#[cfg(desktop)]
+pub(crate) fn focus_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.set_focus();
+    }
+}
+
+/// Links received before the UI subscribed to `link-event`
+#[tauri::command]
+pub fn take_pending_links(pending: State<'_, PendingLinks>) -> Vec<LinkEvent> {
+    std::mem::take(&mut *pending.0.lock().unwrap())
+}
+
+#[tauri::command]
+pub fn parse_deep_link(url: String) -> LinkEvent {
+    parse_link(&url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::share_codec::encode_share_data;
+    use crate::test_support::{share_data, slot};
+
+    fn ticket() -> String {
+        FriendTicket {
+            i: iroh::SecretKey::from_bytes(&[7; 32]).public().to_string(),
+            a: Vec::new(),
+            r: None,
+            k: FriendTicket::generate_invite(),
+        }
+        .encode()
+        .unwrap()
+    }
+
+    #[test]
+    fn ticket_links_are_invites() {
+        let ticket = ticket();
+        for url in [
+            format!("vfriend://connect/{}", ticket),
+            format!("https://vfriend.preetham.top/connect/{}/", ticket),
+            format!("vfriend://{}?from=qr", ticket),
+        ] {
+            match parse_link(&url) {
+                LinkEvent::InviteReceived { ticket: got, .. } => assert_eq!(got, ticket),
+                other => panic!("{} parsed as {:?}", url, other),
+            }
+        }
+    }
+
+    #[test]
+    fn share_links_carry_the_timetable() {
+        let data = share_data("Asha", vec![slot(1, "t", 1, "BCSE101L-TH-AB1-301-ALL")]);
+        let code = encode_share_data(&data).unwrap();
+        for url in [
+            format!("vfriend://share/{}", code),
+            format!("https://vfriend.preetham.top/{}#top", code),
+        ] {
+            match parse_link(&url) {
+                LinkEvent::FriendLinkReceived { share_data, .. } => {
+                    assert_eq!(share_data.u, "Asha");
+                    assert_eq!(share_data.o.len(), 1);
+                }
+                other => panic!("{} parsed as {:?}", url, other),
+            }
+        }
+    }
+
+    #[test]
+    fn legacy_payload_keeps_its_trailing_slash() {
+        match parse_link("vfriend://eyJ1IjoiQXNoYSJ9+ab/") {
+            LinkEvent::LegacyLinkReceived { payload, .. } => {
+                assert_eq!(payload, "eyJ1IjoiQXNoYSJ9+ab/")
+            }
+            other => panic!("parsed as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn other_links_are_invalid() {
+        for url in [
+            "https://example.com/connect/vft1abc",
+            "mailto:someone@example.com",
+            "vfriend://",
+            "https://vfriend.preetham.top/",
+        ] {
+            assert!(
+                matches!(parse_link(url), LinkEvent::InvalidLink { .. }),
+                "{}",
+                url
+            );
+        }
+        assert!(matches!(
+            parse_link("vfriend://connect/vft1!!"),
+            LinkEvent::InvalidLink { .. }
+        ));
+    }
+}
@@ -1,10 +1,445 @@
-struct Building {
-    floors: u8,
-    classesperfloor: u8,
-    distancebwclass: u8,
-    firststeps: u8,
-    normalsteps: u8,
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Average walking pace between blocks, in metres per second
+const WALK_SPEED: f32 = 1.3;
+/// Time to climb or descend one stair step, in seconds
+const SECONDS_PER_STEP: f32 = 0.6;
+/// Rough horizontal distance covered per stair step, in metres
+const METRES_PER_STEP: f32 = 0.3;
+
+pub struct Building {
+    pub code: &'static str,
+    pub floors: u8,
+    pub classesperfloor: u8,
+    pub distancebwclass: u8, // metres between neighbouring rooms
+    pub firststeps: u8,      // steps from the ground floor to the first floor
+    pub normalsteps: u8,     // steps between every higher pair of floors
+}
+
+impl Building {
+    /// Stair steps from the ground floor up to `floor`
+    fn steps_to(&self, floor: u8) -> u32 {
+        match floor.min(self.floors.saturating_sub(1)) {
+            0 => 0,
+            f => self.firststeps as u32 + (f as u32 - 1) * self.normalsteps as u32,
+        }
+    }
+
+    /// Corridor metres from the stairwell (mid-floor) to a room
+    fn corridor_to(&self, number: u8) -> u32 {
+        let middle = self.classesperfloor / 2;
+        number.abs_diff(middle) as u32 * self.distancebwclass as u32
+    }
+}
+
+const CAMPUS: [Building; 9] = [
+    Building {
+        code: "AB1",
+        floors: 7,
+        classesperfloor: 20,
+        distancebwclass: 9,
+        firststeps: 28,
+        normalsteps: 24,
+    },
+    Building {
+        code: "AB2",
+        floors: 8,
+        classesperfloor: 24,
+        distancebwclass: 9,
+        firststeps: 28,
+        normalsteps: 24,
+    },
+    Building {
+        code: "AB3",
+        floors: 9,
+        classesperfloor: 24,
+        distancebwclass: 8,
+        firststeps: 30,
+        normalsteps: 24,
+    },
+    Building {
+        code: "SJT",
+        floors: 9,
+        classesperfloor: 30,
+        distancebwclass: 8,
+        firststeps: 26,
+        normalsteps: 22,
+    },
+    Building {
+        code: "TT",
+        floors: 8,
+        classesperfloor: 24,
+        distancebwclass: 8,
+        firststeps: 26,
+        normalsteps: 22,
+    },
+    Building {
+        code: "MB",
+        floors: 6,
+        classesperfloor: 16,
+        distancebwclass: 10,
+        firststeps: 32,
+        normalsteps: 26,
+    },
+    Building {
+        code: "SMV",
+        floors: 6,
+        classesperfloor: 18,
+        distancebwclass: 9,
+        firststeps: 28,
+        normalsteps: 24,
+    },
+    Building {
+        code: "GDN",
+        floors: 6,
+        classesperfloor: 16,
+        distancebwclass: 9,
+        firststeps: 28,
+        normalsteps: 24,
+    },
+    Building {
+        code: "CDMM",
+        floors: 5,
+        classesperfloor: 14,
+        distancebwclass: 10,
+        firststeps: 30,
+        normalsteps: 24,
+    },
+];
+
+/// Places worth meeting at besides the blocks themselves
+const LANDMARKS: [(&str, SpotKind); 3] = [
+    ("FOODYS", SpotKind::Canteen),
+    ("GAZEBO", SpotKind::Canteen),
+    ("LIBRARY", SpotKind::Library),
+];
+
+/// Graph nodes are the blocks followed by the landmarks
+const NODE_COUNT: usize = CAMPUS.len() + LANDMARKS.len();
+
+/// Walkable paths between block entrances and landmarks, in metres
+const PATHS: [(&str, &str, u32); 22] = [
+    ("AB1", "AB2", 150),
+    ("AB2", "AB3", 210),
+    ("AB1", "MB", 260),
+    ("AB3", "TT", 380),
+    ("MB", "SJT", 300),
+    ("MB", "SMV", 240),
+    ("SJT", "TT", 250),
+    ("SJT", "SMV", 320),
+    ("SJT", "GDN", 350),
+    ("TT", "GDN", 280),
+    ("SMV", "CDMM", 220),
+    ("GDN", "CDMM", 200),
+    ("AB2", "MB", 310),
+    ("AB3", "SJT", 420),
+    ("TT", "CDMM", 430),
+    ("FOODYS", "SJT", 120),
+    ("FOODYS", "TT", 160),
+    ("GAZEBO", "AB2", 90),
+    ("GAZEBO", "AB3", 130),
+    ("LIBRARY", "MB", 110),
+    ("LIBRARY", "AB1", 180),
+    ("LIBRARY", "SMV", 200),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpotKind {
+    Block,
+    Canteen,
+    Library,
+}
+
+/// A node of the campus graph people can meet at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Spot {
+    pub code: String,
+    pub kind: SpotKind,
+}
+
+fn node_index(code: &str) -> Option<usize> {
+    match building(code) {
+        Some((i, _)) => Some(i),
+        None => LANDMARKS
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(code))
+            .map(|i| CAMPUS.len() + i),
+    }
+}
+
+fn node_spot(index: usize) -> Spot {
+    match CAMPUS.get(index) {
+        Some(b) => Spot {
+            code: b.code.to_string(),
+            kind: SpotKind::Block,
+        },
+        None => {
+            let (name, kind) = LANDMARKS[index - CAMPUS.len()];
+            Spot {
+                code: name.to_string(),
+                kind,
+            }
+        }
+    }
+}
+
+fn building(code: &str) -> Option<(usize, &'static Building)> {
+    CAMPUS
+        .iter()
+        .enumerate()
+        .find(|(_, b)| b.code.eq_ignore_ascii_case(code))
+}
+
+/// A room resolved against the campus model
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomCode {
+    pub building: String,
+    pub floor: u8,
+    pub number: u8,
+}
+
+impl RoomCode {
+    /// Find the room in a slot's text: "AB3-206", "BMAT201L-AB3-206",
+    /// the raw "A2-BMAT201L-TH-AB3-206-ALL", or run together as "SJTG01"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens: Vec<&str> = text.split('-').map(str::trim).collect();
+
+        for (i, token) in tokens.iter().enumerate() {
+            if let (Some((_, b)), Some(next)) = (building(token), tokens.get(i + 1)) {
+                if let Some(room) = Self::from_parts(b, next)? {
+                    return Ok(room);
+                }
+            }
+            for b in CAMPUS.iter() {
+                let Some(prefix) = token.get(..b.code.len()) else {
+                    continue;
+                };
+                if prefix.eq_ignore_ascii_case(b.code) {
+                    if let Some(room) = Self::from_parts(b, &token[b.code.len()..])? {
+                        return Ok(room);
+                    }
+                }
+            }
+        }
+        Err(format!("No known room in '{}'", text))
+    }
+
+    /// "206" is floor 2 room 6, "G01" is ground floor room 1; letter
+    /// suffixes such as "503A" are ignored. `None` when `room` is not a
+    /// room number at all.
+    fn from_parts(building: &Building, room: &str) -> Result<Option<Self>, String> {
+        let room = room.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+        let (floor, number) = match room.strip_prefix(['G', 'g']) {
+            Some(rest) => match rest.parse::<u8>() {
+                Ok(number) => (0, number),
+                Err(_) => return Ok(None),
+            },
+            None => {
+                let Ok(value) = room.parse::<u32>() else {
+                    return Ok(None);
+                };
+                let floor = u8::try_from(value / 100)
+                    .map_err(|_| format!("{}-{} has no such floor", building.code, room))?;
+                (floor, (value % 100) as u8)
+            }
+        };
+        Ok(Some(Self {
+            building: building.code.to_string(),
+            floor,
+            number,
+        }))
+    }
+}
+
+/// Estimated walk between two rooms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WalkEstimate {
+    pub from: RoomCode,
+    pub to: RoomCode,
+    pub metres: u32,
+    pub seconds: u32,
+    pub route: Vec<String>, // block and landmark codes in walking order
 }
 
-// 4 buildings
-// let building1 = Building::new();
+/// Dijkstra from one node: metres to every node and each node's predecessor
+fn walk_from(from: usize) -> ([u32; NODE_COUNT], [Option<usize>; NODE_COUNT]) {
+    let mut dist = [u32::MAX; NODE_COUNT];
+    let mut prev = [None; NODE_COUNT];
+    let mut heap = BinaryHeap::new();
+
+    dist[from] = 0;
+    heap.push(Reverse((0, from)));
+
+    while let Some(Reverse((d, node))) = heap.pop() {
+        if d > dist[node] {
+            continue;
+        }
+        for &(a, b, metres) in PATHS.iter() {
+            let (Some(ia), Some(ib)) = (node_index(a), node_index(b)) else {
+                continue;
+            };
+            let next = match node {
+                n if n == ia => ib,
+                n if n == ib => ia,
+                _ => continue,
+            };
+            if d + metres < dist[next] {
+                dist[next] = d + metres;
+                prev[next] = Some(node);
+                heap.push(Reverse((dist[next], next)));
+            }
+        }
+    }
+    (dist, prev)
+}
+
+/// Shortest path between two nodes as (metres, node indices)
+fn shortest_path(from: usize, to: usize) -> Option<(u32, Vec<usize>)> {
+    let (dist, prev) = walk_from(from);
+    if dist[to] == u32::MAX {
+        return None;
+    }
+    let mut route = vec![to];
+    while let Some(p) = prev[*route.last()?] {
+        route.push(p);
+    }
+    route.reverse();
+    Some((dist[to], route))
+}
+
+fn walk_seconds(flat_metres: u32, steps: u32) -> f32 {
+    flat_metres as f32 / WALK_SPEED + steps as f32 * SECONDS_PER_STEP
+}
+
+/// Seconds to walk from a room to every reachable block entrance and landmark
+pub fn seconds_to_spots(room: &RoomCode) -> Vec<(Spot, u32)> {
+    let Some((index, b)) = building(&room.building) else {
+        return Vec::new();
+    };
+    let exit_metres = b.corridor_to(room.number);
+    let exit_steps = b.steps_to(room.floor);
+    let (dist, _) = walk_from(index);
+
+    dist.iter()
+        .enumerate()
+        .filter(|(_, &metres)| metres != u32::MAX)
+        .map(|(i, &metres)| {
+            let seconds = walk_seconds(exit_metres + metres, exit_steps);
+            (node_spot(i), seconds.round() as u32)
+        })
+        .collect()
+}
+
+/// Walking distance and time between two room codes
+pub fn walking_estimate(from: &str, to: &str) -> Result<WalkEstimate, String> {
+    walk_between(RoomCode::parse(from)?, RoomCode::parse(to)?)
+}
+
+/// Walking distance and time between two already parsed rooms
+pub fn walk_between(from: RoomCode, to: RoomCode) -> Result<WalkEstimate, String> {
+    let (from_index, from_building) =
+        building(&from.building).ok_or_else(|| format!("Unknown block {}", from.building))?;
+    let (to_index, to_building) =
+        building(&to.building).ok_or_else(|| format!("Unknown block {}", to.building))?;
+
+    let (flat_metres, steps, route) = if from_index == to_index {
+        let b = from_building;
+        let steps = b.steps_to(from.floor).abs_diff(b.steps_to(to.floor));
+        let corridor = if from.floor == to.floor {
+            from.number.abs_diff(to.number) as u32 * b.distancebwclass as u32
+        } else {
+            b.corridor_to(from.number) + b.corridor_to(to.number)
+        };
+        (corridor, steps, vec![from_index])
+    } else {
+        let (outside, route) = shortest_path(from_index, to_index)
+            .ok_or_else(|| format!("No path from {} to {}", from.building, to.building))?;
+        let corridor = from_building.corridor_to(from.number) + to_building.corridor_to(to.number);
+        let steps = from_building.steps_to(from.floor) + to_building.steps_to(to.floor);
+        (outside + corridor, steps, route)
+    };
+
+    let metres = flat_metres + (steps as f32 * METRES_PER_STEP).round() as u32;
+    let seconds = walk_seconds(flat_metres, steps);
+
+    Ok(WalkEstimate {
+        from,
+        to,
+        metres,
+        seconds: seconds.round() as u32,
+        route: route.into_iter().map(|i| node_spot(i).code).collect(),
+    })
+}
+
+#[tauri::command]
+pub fn estimate_walk(from: String, to: String) -> Result<WalkEstimate, String> {
+    walking_estimate(&from, &to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(text: &str) -> RoomCode {
+        RoomCode::parse(text).unwrap()
+    }
+
+    #[test]
+    fn rooms_are_found_in_slot_text() {
+        let ab3 = RoomCode {
+            building: "AB3".to_string(),
+            floor: 2,
+            number: 6,
+        };
+        assert_eq!(room("AB3-206"), ab3);
+        assert_eq!(room("BMAT201L-AB3-206"), ab3);
+        assert_eq!(room("A2-BMAT201L-TH-AB3-206-ALL"), ab3);
+        assert_eq!(room("sjtG01"), room("SJT-G01"));
+        assert_eq!(room("SJT-G01").floor, 0);
+        assert_eq!(room("TT-503A").number, 3);
+        assert!(RoomCode::parse("XYZ-101").is_err());
+        assert!(RoomCode::parse("BCSE101L-TH").is_err());
+        assert_eq!(room("SJT-25599").floor, 255);
+        assert!(RoomCode::parse("SJT-25600").is_err());
+        assert!(RoomCode::parse("SJT70000").is_err());
+    }
+
+    #[test]
+    fn same_room_is_no_walk() {
+        let walk = walking_estimate("SJT-301", "SJT-301").unwrap();
+        assert_eq!((walk.metres, walk.seconds), (0, 0));
+        assert_eq!(walk.route, vec!["SJT"]);
+    }
+
+    #[test]
+    fn walks_take_the_shortest_route_both_ways() {
+        let there = walking_estimate("AB1-G10", "SJT-G15").unwrap();
+        let back = walking_estimate("SJT-G15", "AB1-G10").unwrap();
+        assert_eq!(there.route, vec!["AB1", "MB", "SJT"]);
+        assert_eq!(there.metres, 560);
+        assert_eq!(there.seconds, back.seconds);
+        assert_eq!(back.route, vec!["SJT", "MB", "AB1"]);
+    }
+
+    #[test]
+    fn stairs_add_time() {
+        let ground = walking_estimate("SJT-G15", "TT-G12").unwrap();
+        let upstairs = walking_estimate("SJT-815", "TT-612").unwrap();
+        assert!(upstairs.seconds > ground.seconds);
+        assert!(upstairs.metres > ground.metres);
+    }
+
+    #[test]
+    fn every_spot_is_reachable_and_own_block_is_nearest() {
+        let spots = seconds_to_spots(&room("SJT-G15"));
+        assert_eq!(spots.len(), NODE_COUNT);
+        let (nearest, seconds) = spots.iter().min_by_key(|(_, s)| *s).unwrap();
+        assert_eq!(nearest.code, "SJT");
+        assert_eq!(*seconds, 0);
+        let foodys = spots.iter().find(|(s, _)| s.code == "FOODYS").unwrap();
+        assert_eq!(foodys.0.kind, SpotKind::Canteen);
+    }
+}
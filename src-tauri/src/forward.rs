@@ -0,0 +1,443 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Nonce,
+};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    PublicKey, SecretKey,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use x25519_dalek::{PublicKey as X25519PublicKey, StaticSecret};
+
+use crate::model::share_data_from_slice;
+use crate::p2p::{unix_timestamp, EventHub, FriendEvent, ShareData};
+use crate::registry::FriendRegistry;
+
+pub const SYNC_ALPN: &[u8] = b"vfriend/sync/1";
+
+/// Undelivered updates are dropped after a week
+const BUNDLE_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+const MAX_CACHED_BUNDLES: usize = 256;
+const MAX_CIPHERTEXT_LEN: usize = 200_000;
+pub(crate) const MAX_SYNC_MESSAGE_LEN: usize = 2_000_000;
+pub(crate) const STALE_BUNDLE: &str = "Bundle is not newer than data we already have";
+
+// ============================================================================
+// Sealed Bundles
+// ============================================================================
+
+/// `ShareData` encrypted for a single recipient and signed by its author, so
+/// a mutual friend can hold on to it without being able to read or alter it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedBundle {
+    pub from: String,       // author endpoint id
+    pub to: String,         // recipient endpoint id
+    pub created_at: u64,    // unix seconds
+    pub expires_at: u64,    // unix seconds
+    pub nonce: String,      // base64
+    pub ciphertext: String, // base64
+    pub signature: String,  // base64 ed25519 over signed_bytes()
+}
+
+impl SealedBundle {
+    pub fn seal(secret_key: &SecretKey, to: &str, share_data: &ShareData) -> Result<Self, String> {
+        let from_key = secret_key.public();
+        let to_key = PublicKey::from_str(to).map_err(|e| format!("Invalid recipient ID: {}", e))?;
+
+        let cipher = bundle_cipher(secret_key, &to_key, &from_key, &to_key)?;
+        let nonce = rand::random::<[u8; 12]>();
+        let plaintext = serde_json::to_vec(share_data)
+            .map_err(|e| format!("Failed to serialize share data: {}", e))?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+            .map_err(|_| "Failed to encrypt share data".to_string())?;
+
+        let created_at = next_created_at();
+        let mut bundle = SealedBundle {
+            from: from_key.to_string(),
+            to: to_key.to_string(),
+            created_at,
+            expires_at: created_at + BUNDLE_TTL_SECS,
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+            signature: String::new(),
+        };
+
+        let signing_key = SigningKey::from_bytes(&secret_key.to_bytes());
+        let signature = signing_key.sign(&bundle.signed_bytes());
+        bundle.signature = STANDARD.encode(signature.to_bytes());
+        Ok(bundle)
+    }
+
+    /// Check the author's signature and size limits; does not decrypt
+    pub fn verify(&self) -> Result<(), String> {
+        if self.ciphertext.len() > MAX_CIPHERTEXT_LEN {
+            return Err("Bundle is too large".to_string());
+        }
+
+        let from_key =
+            PublicKey::from_str(&self.from).map_err(|e| format!("Invalid author ID: {}", e))?;
+        let verifying_key = VerifyingKey::from_bytes(from_key.as_bytes())
+            .map_err(|e| format!("Invalid author key: {}", e))?;
+
+        let signature_bytes = STANDARD
+            .decode(&self.signature)
+            .map_err(|e| format!("Invalid signature encoding: {}", e))?;
+        let signature = Signature::from_slice(&signature_bytes)
+            .map_err(|e| format!("Invalid signature: {}", e))?;
+
+        verifying_key
+            .verify(&self.signed_bytes(), &signature)
+            .map_err(|_| "Bundle signature does not match its author".to_string())
+    }
+
+    /// Verify and decrypt a bundle addressed to us
+    pub fn open(&self, secret_key: &SecretKey) -> Result<ShareData, String> {
+        self.verify()?;
+
+        let my_key = secret_key.public();
+        if self.to != my_key.to_string() {
+            return Err("Bundle is addressed to someone else".to_string());
+        }
+        if self.expires_at <= unix_timestamp() {
+            return Err("Bundle has expired".to_string());
+        }
+
+        let from_key =
+            PublicKey::from_str(&self.from).map_err(|e| format!("Invalid author ID: {}", e))?;
+        let cipher = bundle_cipher(secret_key, &from_key, &from_key, &my_key)?;
+
+        let nonce = STANDARD
+            .decode(&self.nonce)
+            .map_err(|e| format!("Invalid nonce encoding: {}", e))?;
+        if nonce.len() != 12 {
+            return Err("Invalid nonce length".to_string());
+        }
+        let ciphertext = STANDARD
+            .decode(&self.ciphertext)
+            .map_err(|e| format!("Invalid ciphertext encoding: {}", e))?;
+
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| "Failed to decrypt bundle".to_string())?;
+        share_data_from_slice(&plaintext)
+    }
+
+    fn signed_bytes(&self) -> Vec<u8> {
+        format!(
+            "vfriend-bundle|{}|{}|{}|{}|{}|{}",
+            self.from, self.to, self.created_at, self.expires_at, self.nonce, self.ciphertext
+        )
+        .into_bytes()
+    }
+}
+
+/// Recipients refuse bundles that are not strictly newer than the last one,
+/// so two updates sealed within the same second must still differ
+fn next_created_at() -> u64 {
+    static LAST: AtomicU64 = AtomicU64::new(0);
+    let now = unix_timestamp();
+    let previous = LAST
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |last| {
+            Some(now.max(last + 1))
+        })
+        .unwrap();
+    now.max(previous + 1)
+}
+
+/// Derive the symmetric key shared by author and recipient by converting
+/// both ed25519 endpoint keys to x25519 and running Diffie-Hellman
+fn bundle_cipher(
+    secret_key: &SecretKey,
+    peer: &PublicKey,
+    from: &PublicKey,
+    to: &PublicKey,
+) -> Result<ChaCha20Poly1305, String> {
+    let scalar = SigningKey::from_bytes(&secret_key.to_bytes()).to_scalar_bytes();
+    let peer_point = VerifyingKey::from_bytes(peer.as_bytes())
+        .map_err(|e| format!("Invalid peer key: {}", e))?
+        .to_montgomery();
+
+    let shared =
+        StaticSecret::from(scalar).diffie_hellman(&X25519PublicKey::from(peer_point.to_bytes()));
+
+    let mut hasher = Sha256::new();
+    hasher.update(b"vfriend-forward-v1");
+    hasher.update(shared.as_bytes());
+    hasher.update(from.as_bytes());
+    hasher.update(to.as_bytes());
+
+    ChaCha20Poly1305::new_from_slice(&hasher.finalize())
+        .map_err(|_| "Failed to derive bundle key".to_string())
+}
+
+// ============================================================================
+// Forward Cache
+// ============================================================================
+
+/// Bundles we hold for friends of ours who are currently offline. Only the
+/// newest bundle per author/recipient pair is kept.
+#[derive(Debug, Default)]
+pub struct ForwardCache {
+    bundles: Vec<SealedBundle>,
+}
+
+impl ForwardCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn store(&mut self, bundle: SealedBundle) -> Result<(), String> {
+        bundle.verify()?;
+
+        let now = unix_timestamp();
+        if bundle.expires_at <= now {
+            return Err("Bundle has expired".to_string());
+        }
+
+        self.bundles
+            .retain(|b| b.expires_at > now && !(b.from == bundle.from && b.to == bundle.to));
+
+        if self.bundles.len() >= MAX_CACHED_BUNDLES {
+            // Evict whatever would expire first
+            if let Some(idx) = self
+                .bundles
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, b)| b.expires_at)
+                .map(|(idx, _)| idx)
+            {
+                self.bundles.remove(idx);
+            }
+        }
+
+        self.bundles.push(bundle);
+        Ok(())
+    }
+
+    /// Hand over (and forget) everything addressed to `recipient`
+    pub fn take_for(&mut self, recipient: &str) -> Vec<SealedBundle> {
+        let now = unix_timestamp();
+        let (mine, rest): (Vec<_>, Vec<_>) = self
+            .bundles
+            .drain(..)
+            .filter(|b| b.expires_at > now)
+            .partition(|b| b.to == recipient);
+        self.bundles = rest;
+        mine
+    }
+}
+
+// ============================================================================
+// Sync Protocol Messages
+// ============================================================================
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub(crate) enum SyncRequest {
+    /// A bundle for the receiving endpoint itself
+    Deliver { bundle: SealedBundle },
+    /// A bundle to hold for one of the receiver's friends
+    Store { bundle: SealedBundle },
+    /// Ask for bundles held on behalf of the caller
+    Fetch,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct SyncResponse {
+    pub ok: bool,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub bundles: Vec<SealedBundle>,
+}
+
+impl SyncResponse {
+    fn ok(bundles: Vec<SealedBundle>) -> Self {
+        Self {
+            ok: true,
+            error: None,
+            bundles,
+        }
+    }
+
+    fn error(message: String) -> Self {
+        Self {
+            ok: false,
+            error: Some(message),
+            bundles: Vec::new(),
+        }
+    }
+}
+
+// ============================================================================
+// Sync Protocol Handler (Internal)
+// ============================================================================
+
+#[derive(Clone, Debug)]
+pub(crate) struct SyncProtocolHandler {
+    pub secret_key: SecretKey,
+    pub events: EventHub,
+    pub friends: Arc<RwLock<FriendRegistry>>,
+    pub cache: Arc<RwLock<ForwardCache>>,
+}
+
+impl SyncProtocolHandler {
+    async fn handle(
+        &self,
+        remote_id: &str,
+        request: SyncRequest,
+    ) -> Result<Vec<SealedBundle>, String> {
+        match request {
+            SyncRequest::Deliver { bundle } => {
+                let friends = self.friends.read().await;
+                if !friends.is_friend(&bundle.from) {
+                    return Err("Bundle author is not a friend".to_string());
+                }
+                if !friends.is_newer_bundle(&bundle.from, bundle.created_at) {
+                    return Err(STALE_BUNDLE.to_string());
+                }
+                drop(friends);
+                let share_data = bundle.open(&self.secret_key)?;
+
+                // Checked again under the write lock, in case a newer one
+                // arrived while we were decrypting
+                let recorded = self.friends.write().await.record_bundle(
+                    bundle.from.clone(),
+                    bundle.created_at,
+                    share_data.clone(),
+                );
+                if !recorded {
+                    return Err(STALE_BUNDLE.to_string());
+                }
+                self.events.emit(FriendEvent::DataReceived { share_data });
+                Ok(Vec::new())
+            }
+            SyncRequest::Store { bundle } => {
+                // Only carry mail between two people we are friends with
+                let friends = self.friends.read().await;
+                if !friends.is_friend(remote_id) || !friends.is_friend(&bundle.to) {
+                    return Err("Recipient is not a mutual friend".to_string());
+                }
+                drop(friends);
+
+                self.cache.write().await.store(bundle)?;
+                Ok(Vec::new())
+            }
+            SyncRequest::Fetch => Ok(self.cache.write().await.take_for(remote_id)),
+        }
+    }
+}
+
+fn io_error(kind: std::io::ErrorKind, message: String) -> AcceptError {
+    AcceptError::from_err(std::io::Error::new(kind, message))
+}
+
+impl ProtocolHandler for SyncProtocolHandler {
+    async fn accept(&self, connection: Connection) -> std::result::Result<(), AcceptError> {
+        let remote_id = connection.remote_id().to_string();
+
+        let (mut send, mut recv) = connection.accept_bi().await?;
+        let data = recv.read_to_end(MAX_SYNC_MESSAGE_LEN).await.map_err(|e| {
+            io_error(
+                std::io::ErrorKind::Other,
+                format!("Failed to read sync request: {}", e),
+            )
+        })?;
+
+        let response = match serde_json::from_slice::<SyncRequest>(&data) {
+            Ok(request) => match self.handle(&remote_id, request).await {
+                Ok(bundles) => SyncResponse::ok(bundles),
+                Err(message) => SyncResponse::error(message),
+            },
+            Err(e) => SyncResponse::error(format!("Failed to parse sync request: {}", e)),
+        };
+
+        let bytes = serde_json::to_vec(&response).map_err(|e| {
+            io_error(
+                std::io::ErrorKind::InvalidData,
+                format!("Failed to serialize sync response: {}", e),
+            )
+        })?;
+        send.write_all(&bytes).await.map_err(|e| {
+            io_error(
+                std::io::ErrorKind::Other,
+                format!("Failed to send sync response: {}", e),
+            )
+        })?;
+        send.finish().map_err(|e| {
+            io_error(
+                std::io::ErrorKind::Other,
+                format!("Failed to finish sync response: {}", e),
+            )
+        })?;
+
+        connection.closed().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::share_data;
+
+    fn key(seed: u8) -> SecretKey {
+        SecretKey::from_bytes(&[seed; 32])
+    }
+
+    /// A bundle from `from` to `to` that ran out `age` seconds ago, signed
+    /// again so that only its expiry is wrong
+    fn expired(from: &SecretKey, to: &SecretKey, age: u64) -> SealedBundle {
+        let to = to.public().to_string();
+        let mut bundle = SealedBundle::seal(from, &to, &share_data("Asha", Vec::new())).unwrap();
+        bundle.expires_at = unix_timestamp() - age;
+        let signature = SigningKey::from_bytes(&from.to_bytes()).sign(&bundle.signed_bytes());
+        bundle.signature = STANDARD.encode(signature.to_bytes());
+        bundle
+    }
+
+    #[test]
+    fn expired_bundle_is_not_stored() {
+        let bundle = expired(&key(1), &key(2), 1);
+        assert!(bundle.verify().is_ok());
+
+        let mut cache = ForwardCache::new();
+        assert_eq!(cache.store(bundle).unwrap_err(), "Bundle has expired");
+        assert!(cache.bundles.is_empty());
+    }
+
+    #[test]
+    fn expired_bundles_are_evicted() {
+        let (alice, bob, carol) = (key(1), key(2), key(3));
+        let mut cache = ForwardCache::new();
+        // Held while still valid, then left to run out
+        cache.bundles.push(expired(&alice, &bob, 60));
+        cache.bundles.push(expired(&alice, &carol, 60));
+
+        // Storing anything drops what has run out
+        let fresh = SealedBundle::seal(
+            &carol,
+            &bob.public().to_string(),
+            &share_data("Carol", Vec::new()),
+        )
+        .unwrap();
+        cache.store(fresh).unwrap();
+        assert_eq!(cache.bundles.len(), 1);
+
+        // And nothing expired is ever handed over
+        cache.bundles.push(expired(&alice, &bob, 60));
+        let for_bob = cache.take_for(&bob.public().to_string());
+        assert_eq!(for_bob.len(), 1);
+        assert_eq!(for_bob[0].from, carol.public().to_string());
+        assert!(cache.bundles.is_empty());
+    }
+}
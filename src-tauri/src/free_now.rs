@@ -0,0 +1,268 @@
+//! "Who's free right now" for the home screen, for every friend in one call
+//! instead of `build_bitmap` + `build_kindmap` + `get_free_status` each.
+
+use chrono::{Datelike, Local, NaiveTime};
+use serde::Serialize;
+use serde_json::Value;
+use tauri::State;
+
+use crate::distances::RoomCode;
+use crate::location::{day_timeline, LocationInterval, Whereabouts};
+use crate::model::{migrate_share_data_list, ShareData};
+use crate::newercommands::period_bounds;
+use crate::p2p_commands::{current_service, ServiceState};
+
+const DEFAULT_SOON_MINUTES: u32 = 15;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FriendStatus {
+    pub name: String,
+    pub registration: String,
+    pub endpoint_id: Option<String>, // set when read from the registry
+    /// When this changes: the next class for free friends, the end of the
+    /// current run of classes otherwise. `None` is the rest of the day.
+    pub until: Option<NaiveTime>,
+    pub minutes: Option<i64>,   // from now to `until`
+    pub course: Option<String>, // the class they are in or heading to
+    pub room: Option<RoomCode>,
+    pub lunch: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FreeNowReport {
+    pub day: u8,
+    pub time: NaiveTime,
+    pub free: Vec<FriendStatus>,      // longest free first
+    pub free_soon: Vec<FriendStatus>, // soonest first
+    pub busy: Vec<FriendStatus>,      // soonest free first
+}
+
+enum Bucket {
+    Free,
+    FreeSoon,
+    Busy,
+}
+
+fn in_lunch(time: NaiveTime) -> bool {
+    let (Some((_, start)), Some((end, _))) = (period_bounds(6, false), period_bounds(7, false))
+    else {
+        return false;
+    };
+    time >= start && time < end
+}
+
+fn class_of(interval: &LocationInterval) -> (Option<String>, Option<RoomCode>) {
+    match &interval.whereabouts {
+        Whereabouts::InClass { course, room, .. } => (course.clone(), room.clone()),
+        _ => (None, None),
+    }
+}
+
+/// Where one friend stands at `time`
+fn status_at(
+    person: &ShareData,
+    endpoint_id: Option<String>,
+    day: u8,
+    time: NaiveTime,
+    soon_minutes: i64,
+) -> (Bucket, FriendStatus) {
+    let timeline = day_timeline(&person.o, day);
+    let mut status = FriendStatus {
+        name: person.u.clone(),
+        registration: person.r.clone(),
+        endpoint_id,
+        until: None,
+        minutes: None,
+        course: None,
+        room: None,
+        lunch: in_lunch(time),
+    };
+
+    let current = timeline
+        .iter()
+        .position(|i| i.start <= time && time < i.end);
+    let bucket = match current {
+        // Outside the timeline: before the first class or after the last
+        None => {
+            if let Some(first) = timeline.first().filter(|first| time < first.start) {
+                status.until = Some(first.start);
+                (status.course, status.room) = class_of(first);
+            }
+            Bucket::Free
+        }
+        Some(i) if matches!(timeline[i].whereabouts, Whereabouts::Free { .. }) => {
+            if let Some(next) = timeline.get(i + 1) {
+                status.until = Some(next.start);
+                (status.course, status.room) = class_of(next);
+            }
+            Bucket::Free
+        }
+        // In class or walking between back-to-back classes: busy until the
+        // run of classes ends
+        Some(i) => {
+            let run_end = timeline[i..]
+                .iter()
+                .take_while(|interval| !matches!(interval.whereabouts, Whereabouts::Free { .. }))
+                .last()
+                .map(|interval| interval.end);
+            status.until = run_end;
+            let class = timeline[i..]
+                .iter()
+                .find(|interval| matches!(interval.whereabouts, Whereabouts::InClass { .. }));
+            if let Some(class) = class {
+                (status.course, status.room) = class_of(class);
+            }
+            let free_in = run_end.map(|end| (end - time).num_minutes());
+            if free_in.is_some_and(|m| m <= soon_minutes) {
+                Bucket::FreeSoon
+            } else {
+                Bucket::Busy
+            }
+        }
+    };
+    status.minutes = status.until.map(|until| (until - time).num_minutes());
+    (bucket, status)
+}
+
+/// Sort by `until`, with "rest of the day" last, or first when `reverse`
+fn sort_by_until(statuses: &mut [FriendStatus], reverse: bool) {
+    statuses.sort_by(|a, b| {
+        let order = match (a.until, b.until) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        };
+        let order = if reverse { order.reverse() } else { order };
+        order.then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+    });
+}
+
+pub fn free_now(
+    people: Vec<(ShareData, Option<String>)>,
+    day: u8,
+    time: NaiveTime,
+    soon_minutes: u32,
+) -> FreeNowReport {
+    let mut report = FreeNowReport {
+        day,
+        time,
+        free: Vec::new(),
+        free_soon: Vec::new(),
+        busy: Vec::new(),
+    };
+    for (person, endpoint_id) in people {
+        match status_at(&person, endpoint_id, day, time, soon_minutes as i64) {
+            (Bucket::Free, status) => report.free.push(status),
+            (Bucket::FreeSoon, status) => report.free_soon.push(status),
+            (Bucket::Busy, status) => report.busy.push(status),
+        }
+    }
+    sort_by_until(&mut report.free, true);
+    sort_by_until(&mut report.free_soon, false);
+    sort_by_until(&mut report.busy, false);
+    report
+}
+
+/// Free, free within `soon_minutes` and busy friends at `day`/`time`
+/// ("HH:MM"), defaulting to now. Without `friends`, uses the friends the
+/// P2P service knows about.
+#[tauri::command]
+pub async fn friends_free_now(
+    state: State<'_, ServiceState>,
+    friends: Option<Vec<Value>>,
+    day: Option<u8>,
+    time: Option<String>,
+    soon_minutes: Option<u32>,
+) -> Result<FreeNowReport, String> {
+    let people = match friends {
+        Some(friends) => migrate_share_data_list(friends)?
+            .into_iter()
+            .map(|f| (f, None))
+            .collect(),
+        None => current_service(&state)
+            .await?
+            .known_friends()
+            .await
+            .into_iter()
+            .filter_map(|record| Some((record.share_data?, Some(record.endpoint_id))))
+            .collect(),
+    };
+
+    let now = Local::now();
+    let day = day.unwrap_or(now.weekday().number_from_monday() as u8);
+    let time = match time {
+        Some(time) => NaiveTime::parse_from_str(&time, "%H:%M")
+            .map_err(|e| format!("Invalid time '{}': {}", time, e))?,
+        None => now.time(),
+    };
+    Ok(free_now(
+        people,
+        day,
+        time,
+        soon_minutes.unwrap_or(DEFAULT_SOON_MINUTES),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{at, share_data, slot};
+
+    fn person(name: &str, periods: &[u8]) -> (ShareData, Option<String>) {
+        let time_table = periods
+            .iter()
+            .map(|&p| slot(1, "t", p, &format!("BCSE10{}L-TH-SJT-30{}-ALL", p, p)))
+            .collect();
+        (share_data(name, time_table), None)
+    }
+
+    fn names(statuses: &[FriendStatus]) -> Vec<&str> {
+        statuses.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn friends_are_bucketed_and_sorted() {
+        let report = free_now(
+            vec![
+                person("Busy", &[1, 2]), // back to back until 09:45
+                person("Soon", &[1]),    // out at 08:50
+                person("Later", &[3]),   // free until 09:50
+                person("Earlier", &[2]), // free until 08:55
+                person("Day off", &[]),
+            ],
+            1,
+            at(8, 40),
+            15,
+        );
+        assert_eq!(names(&report.free), vec!["Day off", "Later", "Earlier"]);
+        assert_eq!(names(&report.free_soon), vec!["Soon"]);
+        assert_eq!(names(&report.busy), vec!["Busy"]);
+
+        let busy = &report.busy[0];
+        assert_eq!(busy.until, Some(at(9, 45)));
+        assert_eq!(busy.minutes, Some(65));
+        assert_eq!(busy.course.as_deref(), Some("BCSE101L"));
+
+        let later = &report.free[1];
+        assert_eq!(later.minutes, Some(70));
+        assert_eq!(later.course.as_deref(), Some("BCSE103L"));
+        assert_eq!(later.room.as_ref().unwrap().building, "SJT");
+        assert_eq!(report.free[0].until, None);
+    }
+
+    #[test]
+    fn walking_between_classes_is_busy() {
+        let report = free_now(vec![person("Busy", &[1, 2])], 1, at(8, 52), 15);
+        assert_eq!(names(&report.busy), vec!["Busy"]);
+        assert_eq!(report.busy[0].course.as_deref(), Some("BCSE102L"));
+    }
+
+    #[test]
+    fn after_the_last_class_is_free_for_the_day() {
+        let report = free_now(vec![person("Done", &[1])], 1, at(13, 30), 15);
+        assert_eq!(names(&report.free), vec!["Done"]);
+        assert!(report.free[0].until.is_none());
+        assert!(report.free[0].lunch);
+    }
+}
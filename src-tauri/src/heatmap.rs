@@ -0,0 +1,238 @@
+//! Who is free when, across a group of friends and the whole week.
+//!
+//! Each column of the grid is a time range; a friend is free in it when none
+//! of their classes overlap it, using lab timings for lab slots. Friends are
+//! tracked as bits, so a column costs one pass over each friend's classes
+//! and counting is a popcount, which keeps 100+ friends cheap.
+
+use chrono::{NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::model::{migrate_share_data_list, CompactSlot, ShareData};
+use crate::newercommands::period_bounds;
+
+const STEP_MINUTES: u16 = 5;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeatmapResolution {
+    /// Five-minute columns from the start of period 1 to the end of period 12
+    #[default]
+    FiveMinutes,
+    /// One column per theory period, plus one for lunch
+    Period,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HeatmapCell {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub lunch: bool,
+    pub free_count: usize,
+    pub free: Vec<usize>, // indices into the friends passed in
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DayHeatmap {
+    pub day: u8,
+    pub cells: Vec<HeatmapCell>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Heatmap {
+    pub people: Vec<String>,
+    pub days: Vec<DayHeatmap>,
+}
+
+/// A set of friend indices
+#[derive(Debug, Clone)]
+struct FriendSet(Vec<u64>);
+
+impl FriendSet {
+    fn new(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(64)])
+    }
+
+    fn insert(&mut self, index: usize) {
+        self.0[index / 64] |= 1 << (index % 64);
+    }
+
+    fn len(&self) -> usize {
+        self.0.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    fn indices(&self) -> Vec<usize> {
+        let mut indices = Vec::with_capacity(self.len());
+        for (w, &word) in self.0.iter().enumerate() {
+            let mut bits = word;
+            while bits != 0 {
+                indices.push(w * 64 + bits.trailing_zeros() as usize);
+                bits &= bits - 1;
+            }
+        }
+        indices
+    }
+}
+
+fn minute_of_day(time: NaiveTime) -> u16 {
+    (time.hour() * 60 + time.minute()) as u16
+}
+
+fn time_of(minute: u16) -> NaiveTime {
+    NaiveTime::from_hms_opt(minute as u32 / 60, minute as u32 % 60, 0).unwrap()
+}
+
+fn bound(period: u8, start: bool) -> u16 {
+    let (s, e) = period_bounds(period, false).unwrap();
+    minute_of_day(if start { s } else { e })
+}
+
+/// Column ranges in minutes of the day, with whether each is lunch
+fn columns(resolution: HeatmapResolution) -> Vec<(u16, u16, bool)> {
+    let (lunch_start, lunch_end) = (bound(6, false), bound(7, true));
+    match resolution {
+        HeatmapResolution::FiveMinutes => (bound(1, true)..bound(12, false))
+            .step_by(STEP_MINUTES as usize)
+            .map(|m| {
+                let end = m + STEP_MINUTES;
+                (m, end, m >= lunch_start && end <= lunch_end)
+            })
+            .collect(),
+        HeatmapResolution::Period => {
+            let mut columns: Vec<(u16, u16, bool)> = (1..=12)
+                .map(|p| (bound(p, true), bound(p, false), false))
+                .collect();
+            columns.insert(6, (lunch_start, lunch_end, true));
+            columns
+        }
+    }
+}
+
+/// Class times on `day`, in minutes of the day
+fn busy_on(time_table: &[CompactSlot], day: u8) -> Vec<(u16, u16)> {
+    time_table
+        .iter()
+        .filter(|slot| slot.d == day)
+        .filter_map(|slot| period_bounds(slot.p, slot.s == "l"))
+        .map(|(start, end)| (minute_of_day(start), minute_of_day(end)))
+        .collect()
+}
+
+pub fn build_heatmap(people: &[ShareData], resolution: HeatmapResolution) -> Heatmap {
+    let columns = columns(resolution);
+
+    let days = (1..=7)
+        .map(|day| {
+            let mut free = vec![FriendSet::new(people.len()); columns.len()];
+            for (i, person) in people.iter().enumerate() {
+                let busy = busy_on(&person.o, day);
+                for (c, &(start, end, _)) in columns.iter().enumerate() {
+                    if !busy.iter().any(|&(s, e)| s < end && start < e) {
+                        free[c].insert(i);
+                    }
+                }
+            }
+
+            let cells = columns
+                .iter()
+                .zip(free)
+                .map(|(&(start, end, lunch), set)| HeatmapCell {
+                    start: time_of(start),
+                    end: time_of(end),
+                    lunch,
+                    free_count: set.len(),
+                    free: set.indices(),
+                })
+                .collect();
+            DayHeatmap { day, cells }
+        })
+        .collect();
+
+    Heatmap {
+        people: people.iter().map(|p| p.u.clone()).collect(),
+        days,
+    }
+}
+
+/// How many and which friends are free in each column, Monday to Sunday
+#[tauri::command]
+pub fn availability_heatmap(
+    friends: Vec<Value>,
+    resolution: Option<HeatmapResolution>,
+) -> Result<Heatmap, String> {
+    let friends = migrate_share_data_list(friends)?;
+    Ok(build_heatmap(&friends, resolution.unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{at, share_data, slot};
+
+    fn person(name: &str, classes: &[(u8, &str, u8)]) -> ShareData {
+        let time_table = classes
+            .iter()
+            .map(|&(d, s, p)| slot(d, s, p, &format!("{}-{}", name, p)))
+            .collect();
+        share_data(name, time_table)
+    }
+
+    fn cell(map: &Heatmap, day: u8, hour: u32, minute: u32) -> &HeatmapCell {
+        let start = at(hour, minute);
+        map.days[day as usize - 1]
+            .cells
+            .iter()
+            .find(|c| c.start == start)
+            .unwrap()
+    }
+
+    #[test]
+    fn period_columns_include_lunch() {
+        let map = build_heatmap(&[], HeatmapResolution::Period);
+        assert_eq!(map.days.len(), 7);
+        let cells = &map.days[0].cells;
+        assert_eq!(cells.len(), 13);
+        assert!(cells[6].lunch);
+        assert_eq!(cells.iter().filter(|c| c.lunch).count(), 1);
+    }
+
+    #[test]
+    fn classes_mark_friends_busy() {
+        let people = [person("Sana", &[(1, "t", 1)]), person("Rahul", &[])];
+        let map = build_heatmap(&people, HeatmapResolution::FiveMinutes);
+        assert_eq!(cell(&map, 1, 8, 0).free, vec![1]);
+        assert_eq!(cell(&map, 1, 8, 45).free_count, 1);
+        assert_eq!(cell(&map, 1, 8, 50).free, vec![0, 1]);
+        assert_eq!(cell(&map, 2, 8, 0).free_count, 2);
+    }
+
+    #[test]
+    fn labs_use_lab_timings() {
+        // Lab period 2 is 08:50-09:40, theory period 2 08:55-09:45
+        let map = build_heatmap(
+            &[person("Sana", &[(1, "l", 2)])],
+            HeatmapResolution::FiveMinutes,
+        );
+        assert_eq!(cell(&map, 1, 8, 50).free_count, 0);
+        assert_eq!(cell(&map, 1, 9, 40).free_count, 1);
+    }
+
+    #[test]
+    fn more_than_64_friends_are_counted() {
+        let people: Vec<ShareData> = (0..130)
+            .map(|i| {
+                person(
+                    &i.to_string(),
+                    if i % 2 == 0 { &[(3, "t", 4)] } else { &[] },
+                )
+            })
+            .collect();
+        let map = build_heatmap(&people, HeatmapResolution::Period);
+        let fourth = &map.days[2].cells[3];
+        assert_eq!(fourth.free_count, 65);
+        assert!(fourth.free.iter().all(|i| i % 2 == 1));
+        assert_eq!(*fourth.free.last().unwrap(), 129);
+        assert_eq!(map.days[2].cells[0].free_count, 130);
+    }
+}
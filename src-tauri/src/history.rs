@@ -0,0 +1,300 @@
+//! Past timetables for each person, so a re-import or sync can show what
+//! changed instead of silently replacing the old one.
+//!
+//! Kept in `history.json` under each person's registration number (the
+//! username when there is none), oldest version first.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use tauri::AppHandle;
+use tauri_plugin_store::StoreExt;
+
+use crate::location::course_code;
+use crate::model::{migrate_share_data, CompactSlot, ShareData};
+use crate::p2p::unix_timestamp;
+
+const HISTORY_STORE: &str = "history.json";
+/// Oldest versions are dropped past this many per person
+const MAX_VERSIONS: usize = 32;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimetableVersion {
+    pub semester: u32,
+    pub timestamp: String, // `t` of the share data it came from
+    pub recorded_at: u64,
+    pub time_table: Vec<CompactSlot>,
+}
+
+/// One course before and after; `before` is empty when it was added and
+/// `after` when it was dropped
+#[derive(Debug, Clone, Serialize)]
+pub struct CourseChange {
+    pub course: String,
+    pub before: Vec<CompactSlot>,
+    pub after: Vec<CompactSlot>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TimetableDiff {
+    pub added: Vec<CourseChange>,
+    pub removed: Vec<CourseChange>,
+    pub moved: Vec<CourseChange>, // different periods or room
+}
+
+impl TimetableDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.moved.is_empty()
+    }
+}
+
+fn store_key(person: &str) -> String {
+    person.trim().to_uppercase()
+}
+
+/// Store key for the owner of a timetable
+pub fn person_key(share_data: &ShareData) -> String {
+    if share_data.r.trim().is_empty() {
+        store_key(&share_data.u)
+    } else {
+        store_key(&share_data.r)
+    }
+}
+
+fn slot_order(slot: &CompactSlot) -> (u8, u8, bool) {
+    (slot.d, slot.p, slot.s == "l")
+}
+
+/// Slots grouped by course, each group sorted by day and period
+fn by_course(time_table: &[CompactSlot]) -> BTreeMap<String, Vec<CompactSlot>> {
+    let mut courses: BTreeMap<String, Vec<CompactSlot>> = BTreeMap::new();
+    for slot in time_table {
+        let course = course_code(&slot.f).unwrap_or_else(|| slot.f.clone());
+        courses.entry(course).or_default().push(slot.clone());
+    }
+    for slots in courses.values_mut() {
+        slots.sort_by_key(slot_order);
+        slots.dedup();
+    }
+    courses
+}
+
+/// Classes added, dropped or moved between two timetables
+pub fn diff(old: &[CompactSlot], new: &[CompactSlot]) -> TimetableDiff {
+    let mut old = by_course(old);
+    let new = by_course(new);
+    let mut result = TimetableDiff::default();
+
+    for (course, after) in new {
+        match old.remove(&course) {
+            None => result.added.push(CourseChange {
+                course,
+                before: Vec::new(),
+                after,
+            }),
+            Some(before) if before != after => result.moved.push(CourseChange {
+                course,
+                before,
+                after,
+            }),
+            Some(_) => {}
+        }
+    }
+    result
+        .removed
+        .extend(old.into_iter().map(|(course, before)| CourseChange {
+            course,
+            before,
+            after: Vec::new(),
+        }));
+    result
+}
+
+fn same_slots(a: &[CompactSlot], b: &[CompactSlot]) -> bool {
+    let set = |slots: &[CompactSlot]| -> BTreeSet<(u8, u8, String, String)> {
+        slots
+            .iter()
+            .map(|s| (s.d, s.p, s.s.clone(), s.f.clone()))
+            .collect()
+    };
+    set(a) == set(b)
+}
+
+/// Append `share_data` to a person's versions, or only note the newer
+/// timestamp when the timetable is unchanged. Returns what changed since the
+/// previous version, or `None` for the first version or an unchanged re-sync.
+fn push_version(
+    versions: &mut Vec<TimetableVersion>,
+    share_data: &ShareData,
+) -> Option<TimetableDiff> {
+    let previous = match versions.last_mut() {
+        Some(last)
+            if last.semester == share_data.s && same_slots(&last.time_table, &share_data.o) =>
+        {
+            last.timestamp = share_data.t.clone();
+            return None;
+        }
+        Some(last) => Some(diff(&last.time_table, &share_data.o)),
+        None => None,
+    };
+    versions.push(TimetableVersion {
+        semester: share_data.s,
+        timestamp: share_data.t.clone(),
+        recorded_at: unix_timestamp(),
+        time_table: share_data.o.clone(),
+    });
+    if versions.len() > MAX_VERSIONS {
+        versions.drain(..versions.len() - MAX_VERSIONS);
+    }
+    previous
+}
+
+fn load_versions(app: &AppHandle, person: &str) -> Result<Vec<TimetableVersion>, String> {
+    let store = app
+        .store(HISTORY_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", HISTORY_STORE, e))?;
+    match store.get(person) {
+        Some(value) => serde_json::from_value(value)
+            .map_err(|e| format!("Failed to parse history for {}: {}", person, e)),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Save a timetable into its owner's history, before the frontend replaces
+/// the stored copy. Returns what changed since the previous version, or
+/// `None` for the first version or an unchanged re-sync.
+#[tauri::command]
+pub fn record_timetable(
+    app_handle: AppHandle,
+    share_data: Value,
+) -> Result<Option<TimetableDiff>, String> {
+    let share_data = migrate_share_data(share_data)?;
+    let person = person_key(&share_data);
+    if person.is_empty() {
+        return Err("Timetable has no registration number or username".to_string());
+    }
+    let mut versions = load_versions(&app_handle, &person)?;
+    // Saved even when unchanged, so the newer timestamp is kept
+    let changes = push_version(&mut versions, &share_data);
+
+    let store = app_handle
+        .store(HISTORY_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", HISTORY_STORE, e))?;
+    let value = serde_json::to_value(&versions)
+        .map_err(|e| format!("Failed to serialize history: {}", e))?;
+    store.set(person, value);
+    store
+        .save()
+        .map_err(|e| format!("Failed to save {}: {}", HISTORY_STORE, e))?;
+
+    Ok(changes)
+}
+
+/// Versions for a registration number (or username), oldest first,
+/// optionally for one semester only
+#[tauri::command]
+pub fn timetable_history(
+    app_handle: AppHandle,
+    person: String,
+    semester: Option<u32>,
+) -> Result<Vec<TimetableVersion>, String> {
+    let versions = load_versions(&app_handle, &store_key(&person))?;
+    Ok(versions
+        .into_iter()
+        .filter(|v| semester.is_none_or(|s| v.semester == s))
+        .collect())
+}
+
+#[tauri::command]
+pub fn diff_timetables(old: Vec<CompactSlot>, new: Vec<CompactSlot>) -> TimetableDiff {
+    diff(&old, &new)
+}
+
+/// Remove someone's history, e.g. when they are unfriended
+#[tauri::command]
+pub fn clear_timetable_history(app_handle: AppHandle, person: String) -> Result<(), String> {
+    let store = app_handle
+        .store(HISTORY_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", HISTORY_STORE, e))?;
+    store.delete(store_key(&person));
+    store
+        .save()
+        .map_err(|e| format!("Failed to save {}: {}", HISTORY_STORE, e))
+}
+
+/// People with any recorded history
+#[tauri::command]
+pub fn timetable_history_people(app_handle: AppHandle) -> Result<Vec<String>, String> {
+    let store = app_handle
+        .store(HISTORY_STORE)
+        .map_err(|e| format!("Failed to open {}: {}", HISTORY_STORE, e))?;
+    Ok(store
+        .entries()
+        .into_iter()
+        .filter(|(_, value)| matches!(value, Value::Array(v) if !v.is_empty()))
+        .map(|(key, _)| key)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{share_data, slot};
+
+    fn synced(timestamp: &str, time_table: Vec<CompactSlot>) -> ShareData {
+        ShareData {
+            r: "23BCE0001".to_string(),
+            t: timestamp.to_string(),
+            ..share_data("Sana", time_table)
+        }
+    }
+
+    #[test]
+    fn unchanged_resync_keeps_one_version_with_the_newer_timestamp() {
+        let time_table = vec![slot(1, "t", 1, "BCSE101L-AB1-301")];
+        let mut versions = Vec::new();
+        assert!(push_version(&mut versions, &synced("1", time_table.clone())).is_none());
+        assert!(push_version(&mut versions, &synced("2", time_table)).is_none());
+
+        assert_eq!(versions.len(), 1);
+        assert_eq!(versions[0].timestamp, "2");
+    }
+
+    #[test]
+    fn changed_timetable_is_a_new_version_with_its_diff() {
+        let mut versions = Vec::new();
+        push_version(
+            &mut versions,
+            &synced("1", vec![slot(1, "t", 1, "BCSE101L-AB1-301")]),
+        );
+        let changes = push_version(
+            &mut versions,
+            &synced(
+                "2",
+                vec![
+                    slot(2, "t", 3, "BCSE101L-AB1-301"),
+                    slot(1, "t", 1, "BMAT201L-SJT-101"),
+                ],
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(versions.len(), 2);
+        assert_eq!(changes.moved.len(), 1);
+        assert_eq!(changes.added.len(), 1);
+        assert!(changes.removed.is_empty());
+    }
+
+    #[test]
+    fn only_the_newest_versions_are_kept() {
+        let mut versions = Vec::new();
+        for p in 0..(MAX_VERSIONS as u8 + 3) {
+            push_version(
+                &mut versions,
+                &synced("", vec![slot(1, "t", p, "BCSE101L")]),
+            );
+        }
+        assert_eq!(versions.len(), MAX_VERSIONS);
+        assert_eq!(versions[0].time_table[0].p, 3);
+    }
+}
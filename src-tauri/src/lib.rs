@@ -1,24 +1,45 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 pub mod newercommands;
 mod parse_html;
-use std::sync::Arc;
-use tokio::sync::Mutex;
 
 // pub mod commands;
 // pub mod newcommands;
 // mod scheduling_conflict;
 use tauri_plugin_deep_link::DeepLinkExt;
-mod p2p;
+mod advert;
+mod backup;
+mod deep_link;
+pub mod distances;
+pub mod forward;
+mod free_now;
+pub mod heatmap;
+pub mod history;
+pub mod location;
+pub mod meeting;
+pub mod model;
+pub mod notifications;
+pub mod p2p;
+mod p2p_commands;
+pub mod proximity;
+pub mod qr_chunks;
+pub mod registry;
+pub mod share_codec;
+pub mod stats;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod ticket;
+pub mod transitions;
+pub mod validation;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     let mut builder = tauri::Builder::default().plugin(tauri_plugin_os::init());
     #[cfg(desktop)]
     {
-        builder = builder.plugin(tauri_plugin_single_instance::init(|_app, argv, _cwd| {
-        println!("a new app instance was opened with {argv:?} and the deep link event was already triggered");
-        // when defining deep link schemes at runtime, you must also check `argv` here
-    }));
+        builder = builder.plugin(tauri_plugin_single_instance::init(|app, _argv, _cwd| {
+            // The deep-link feature already forwarded any URL in argv to on_open_url
+            deep_link::focus_main_window(app);
+        }));
     }
     #[cfg(mobile)]
     {
@@ -32,11 +53,16 @@ pub fn run() {
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_store::Builder::new().build())
-        .manage(Arc::new(Mutex::new(None::<p2p::FriendExchangeService>)) as p2p::ServiceState)
+        .plugin(tauri_plugin_notification::init())
+        .manage(p2p_commands::ServiceState::default())
+        .manage(deep_link::PendingLinks::default())
+        .manage(qr_chunks::QrScanState::default())
+        .manage(notifications::NotificationState::default())
         .setup(|_app| Ok(()))
         .setup(|app| {
             #[cfg(desktop)]
             app.deep_link().register("vfriend")?;
+            deep_link::init(app.handle());
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -48,20 +74,83 @@ pub fn run() {
             newercommands::new_get_free_status,
             newercommands::currently_at,
             newercommands::currentbit,
+            distances::estimate_walk,
+            location::location_timeline,
+            location::locations_after,
+            meeting::suggest_meeting_point,
+            transitions::check_transitions,
+            share_codec::encode_share_link,
+            share_codec::decode_share_link,
+            deep_link::take_pending_links,
+            deep_link::parse_deep_link,
+            qr_chunks::split_share_qr,
+            qr_chunks::scan_qr_frame,
+            qr_chunks::reset_qr_scan,
+            qr_chunks::join_qr_frames,
+            backup::export_backup,
+            backup::import_backup,
+            model::upgrade_share_data,
+            validation::validate_timetable,
+            history::record_timetable,
+            history::timetable_history,
+            history::timetable_history_people,
+            history::diff_timetables,
+            history::clear_timetable_history,
+            notifications::set_notification_timetables,
+            notifications::set_watch_rules,
+            notifications::set_academic_calendar,
+            notifications::upcoming_alerts,
+            notifications::request_notification_permission,
+            stats::timetable_stats,
+            stats::people_stats,
+            heatmap::availability_heatmap,
+            free_now::friends_free_now,
             // P2P
-            p2p::init_friend_service,
-            p2p::set_share_data,
-            p2p::start_friend_service,
-            p2p::start_discovery,
-            p2p::send_friend_request,
-            p2p::accept_friend_request,
-            p2p::reject_friend_request,
-            p2p::get_my_endpoint_id,
-            p2p::stop_discovery,
+            p2p_commands::init_friend_service,
+            p2p_commands::set_share_data,
+            p2p_commands::start_friend_service,
+            p2p_commands::start_discovery,
+            p2p_commands::send_friend_request,
+            p2p_commands::create_friend_ticket,
+            p2p_commands::send_friend_request_with_ticket,
+            p2p_commands::accept_friend_request,
+            p2p_commands::publish_share_update,
+            p2p_commands::fetch_forwarded_updates,
+            p2p_commands::reject_friend_request,
+            p2p_commands::get_my_endpoint_id,
+            p2p_commands::stop_discovery,
+            p2p_commands::list_nearby_peers,
+            p2p_commands::set_known_friends,
+            p2p_commands::set_proximity_enabled,
+            p2p_commands::set_nearby_watch,
+            p2p_commands::get_nearby_history,
+            p2p_commands::shutdown_friend_service,
+            p2p_commands::restart_friend_service,
+            p2p_commands::friend_service_status,
             // commands::check_conflicts,
             // commands::find_free_times,
             // commands::is_free_at,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| match event {
+            tauri::RunEvent::Exit => p2p_commands::shutdown_on_exit(app),
+            // Lifecycle, not focus: a system dialog or the notification shade
+            // takes focus without backgrounding the app. These are Android's
+            // onPause/onResume and iOS's resign active/enter foreground.
+            #[cfg(mobile)]
+            tauri::RunEvent::WindowEvent {
+                event: tauri::WindowEvent::Suspended,
+                ..
+            } => p2p_commands::set_backgrounded(app, true),
+            #[cfg(mobile)]
+            tauri::RunEvent::WindowEvent {
+                event: tauri::WindowEvent::Resumed,
+                ..
+            } => {
+                p2p_commands::set_backgrounded(app, false);
+                notifications::refresh(app);
+            }
+            _ => {}
+        });
 }
@@ -0,0 +1,236 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+use crate::distances::RoomCode;
+use crate::newercommands::{period_bounds, CompactSlot};
+
+/// Gaps no longer than this are spent walking to the next class
+const TRANSIT_GAP_MINUTES: i64 = 10;
+
+/// Where someone is during one stretch of the day
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Whereabouts {
+    InClass {
+        course: Option<String>,
+        room: Option<RoomCode>,
+        raw: String, // the slot's full text, for anything we could not parse
+    },
+    /// Between two back-to-back classes
+    Transit {
+        from: Option<RoomCode>,
+        to: Option<RoomCode>,
+    },
+    /// Free; `last_seen` is where their previous class was
+    Free {
+        last_seen: Option<RoomCode>,
+        next_class: Option<RoomCode>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocationInterval {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    pub periods: Vec<u8>, // timetable periods covered, empty outside classes
+    pub whereabouts: Whereabouts,
+}
+
+/// Course code in a slot's text, e.g. "BMAT201L" in "BMAT201L-AB3-206"
+pub fn course_code(f: &str) -> Option<String> {
+    f.split('-')
+        .map(str::trim)
+        .find(|t| {
+            t.len() >= 6
+                && t.starts_with(|c: char| c.is_ascii_alphabetic())
+                && t.chars().any(|c| c.is_ascii_digit())
+        })
+        .map(str::to_string)
+}
+
+struct Class {
+    start: NaiveTime,
+    end: NaiveTime,
+    periods: Vec<u8>,
+    raw: String,
+    room: Option<RoomCode>,
+}
+
+/// Classes on `day` in order, with consecutive periods of the same course
+/// (labs) merged into one
+fn classes_on(time_table: &[CompactSlot], day: u8) -> Vec<Class> {
+    let mut slots: Vec<&CompactSlot> = time_table.iter().filter(|slot| slot.d == day).collect();
+    slots.sort_by_key(|slot| slot.p);
+
+    let mut classes: Vec<Class> = Vec::new();
+    for slot in slots {
+        let Some((start, end)) = period_bounds(slot.p, slot.s == "l") else {
+            continue;
+        };
+        if let Some(last) = classes.last_mut() {
+            if last.raw == slot.f && last.end == start {
+                last.end = end;
+                last.periods.push(slot.p);
+                continue;
+            }
+        }
+        classes.push(Class {
+            start,
+            end,
+            periods: vec![slot.p],
+            raw: slot.f.clone(),
+            room: RoomCode::parse(&slot.f).ok(),
+        });
+    }
+    classes
+}
+
+/// The whole day from the first class until the last period ends
+pub fn day_timeline(time_table: &[CompactSlot], day: u8) -> Vec<LocationInterval> {
+    let day_end = period_bounds(12, false).map(|(_, end)| end).unwrap();
+    let mut timeline = Vec::new();
+    let mut previous: Option<&Class> = None;
+
+    let classes = classes_on(time_table, day);
+    for class in &classes {
+        if let Some(prev) = previous {
+            if class.start > prev.end {
+                let whereabouts = if (class.start - prev.end).num_minutes() <= TRANSIT_GAP_MINUTES {
+                    Whereabouts::Transit {
+                        from: prev.room.clone(),
+                        to: class.room.clone(),
+                    }
+                } else {
+                    Whereabouts::Free {
+                        last_seen: prev.room.clone(),
+                        next_class: class.room.clone(),
+                    }
+                };
+                timeline.push(LocationInterval {
+                    start: prev.end,
+                    end: class.start,
+                    periods: Vec::new(),
+                    whereabouts,
+                });
+            }
+        }
+
+        timeline.push(LocationInterval {
+            start: class.start,
+            end: class.end,
+            periods: class.periods.clone(),
+            whereabouts: Whereabouts::InClass {
+                course: course_code(&class.raw),
+                room: class.room.clone(),
+                raw: class.raw.clone(),
+            },
+        });
+        previous = Some(class);
+    }
+
+    if let Some(last) = previous {
+        if last.end < day_end {
+            timeline.push(LocationInterval {
+                start: last.end,
+                end: day_end,
+                periods: Vec::new(),
+                whereabouts: Whereabouts::Free {
+                    last_seen: last.room.clone(),
+                    next_class: None,
+                },
+            });
+        }
+    }
+    timeline
+}
+
+/// Day timeline for a friend, as structured locations
+#[tauri::command]
+pub fn location_timeline(time_table: Vec<CompactSlot>, day: u8) -> Vec<LocationInterval> {
+    day_timeline(&time_table, day)
+}
+
+/// Where a friend is from `time` ("HH:MM") onwards: the interval in progress
+/// and everything after it
+#[tauri::command]
+pub fn locations_after(
+    time_table: Vec<CompactSlot>,
+    day: u8,
+    time: String,
+) -> Result<Vec<LocationInterval>, String> {
+    let time = NaiveTime::parse_from_str(&time, "%H:%M")
+        .map_err(|e| format!("Invalid time '{}': {}", time, e))?;
+    Ok(day_timeline(&time_table, day)
+        .into_iter()
+        .filter(|interval| interval.end > time)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{at, slot};
+
+    #[test]
+    fn course_code_skips_slot_names() {
+        assert_eq!(
+            course_code("A2-BMAT201L-TH-AB3-206-ALL").as_deref(),
+            Some("BMAT201L")
+        );
+        assert_eq!(course_code("L31-AB1"), None);
+    }
+
+    #[test]
+    fn lab_periods_merge_into_one_class() {
+        let lab = "L1-BCSE101P-LO-AB1-702-ALL";
+        let timeline = day_timeline(&[slot(2, "l", 2, lab), slot(2, "l", 1, lab)], 2);
+        assert_eq!(timeline.len(), 2);
+        assert_eq!((timeline[0].start, timeline[0].end), (at(8, 0), at(9, 40)));
+        assert_eq!(timeline[0].periods, vec![1, 2]);
+        match &timeline[0].whereabouts {
+            Whereabouts::InClass { course, room, .. } => {
+                assert_eq!(course.as_deref(), Some("BCSE101P"));
+                assert_eq!(room.as_ref().unwrap().building, "AB1");
+            }
+            other => panic!("expected a class, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn short_gaps_are_transit_and_long_ones_free() {
+        let timeline = day_timeline(
+            &[
+                slot(2, "t", 1, "A1-BCSE101L-TH-AB1-702-ALL"),
+                slot(2, "t", 2, "B1-BMAT201L-TH-SJT-301-ALL"),
+                slot(2, "t", 4, "D1-BPHY101L-TH-TT-101-ALL"),
+            ],
+            2,
+        );
+        let kinds: Vec<_> = timeline
+            .iter()
+            .map(|i| match i.whereabouts {
+                Whereabouts::InClass { .. } => "class",
+                Whereabouts::Transit { .. } => "transit",
+                Whereabouts::Free { .. } => "free",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec!["class", "transit", "class", "free", "class", "free"]
+        );
+        let last = timeline.last().unwrap();
+        assert_eq!((last.start, last.end), (at(11, 35), at(19, 25)));
+        assert!(matches!(
+            last.whereabouts,
+            Whereabouts::Free {
+                next_class: None,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn empty_day_has_no_timeline() {
+        assert!(day_timeline(&[slot(2, "t", 1, "BCSE101L")], 3).is_empty());
+    }
+}
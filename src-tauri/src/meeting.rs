@@ -0,0 +1,272 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::distances::{seconds_to_spots, RoomCode, Spot};
+use crate::location::{day_timeline, LocationInterval, Whereabouts};
+use crate::newercommands::{period_bounds, CompactSlot};
+
+/// Windows shorter than this aren't worth walking anywhere for
+const DEFAULT_MIN_WINDOW_MINUTES: i64 = 20;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MeetingMember {
+    pub name: String,
+    pub time_table: Vec<CompactSlot>,
+}
+
+/// What "best" means when picking a spot
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MeetingObjective {
+    /// Least walking for the group as a whole
+    #[default]
+    Total,
+    /// Nobody walks much longer than anyone else
+    Max,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MemberWalk {
+    pub name: String,
+    pub from: Option<RoomCode>, // None when they have no class before the window
+    pub seconds: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MeetingSuggestion {
+    pub window_start: NaiveTime,
+    pub window_end: NaiveTime,
+    pub spot: Spot,
+    pub total_seconds: u32,
+    pub max_seconds: u32,
+    /// Minutes left together once the last person arrives
+    pub remaining_minutes: i64,
+    pub walks: Vec<MemberWalk>,
+}
+
+struct Busy {
+    start: NaiveTime,
+    end: NaiveTime,
+    room: Option<RoomCode>,
+}
+
+fn busy_intervals(timeline: Vec<LocationInterval>) -> Vec<Busy> {
+    timeline
+        .into_iter()
+        .filter_map(|interval| match interval.whereabouts {
+            Whereabouts::InClass { room, .. } => Some(Busy {
+                start: interval.start,
+                end: interval.end,
+                room,
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Earliest stretch from `after` on where nobody is in class, at least
+/// `min_minutes` long
+fn common_window(
+    schedules: &[Vec<Busy>],
+    after: NaiveTime,
+    min_minutes: i64,
+) -> Option<(NaiveTime, NaiveTime)> {
+    let day_end = period_bounds(12, false)?.1;
+    let mut candidates: Vec<NaiveTime> = schedules
+        .iter()
+        .flatten()
+        .map(|busy| busy.end)
+        .filter(|&end| end >= after)
+        .collect();
+    candidates.push(after);
+    candidates.sort();
+    candidates.dedup();
+
+    for start in candidates {
+        let in_class = schedules
+            .iter()
+            .flatten()
+            .any(|busy| busy.start <= start && start < busy.end);
+        if in_class || start >= day_end {
+            continue;
+        }
+        let end = schedules
+            .iter()
+            .flatten()
+            .map(|busy| busy.start)
+            .filter(|&s| s > start)
+            .min()
+            .unwrap_or(day_end);
+        if (end - start).num_minutes() >= min_minutes {
+            return Some((start, end));
+        }
+    }
+    None
+}
+
+/// Pick where a group should meet in their next common free window, based
+/// on where each person's last class before it was
+pub fn suggest(
+    members: &[MeetingMember],
+    day: u8,
+    after: NaiveTime,
+    objective: MeetingObjective,
+    min_minutes: i64,
+) -> Result<MeetingSuggestion, String> {
+    let schedules: Vec<Vec<Busy>> = members
+        .iter()
+        .map(|member| busy_intervals(day_timeline(&member.time_table, day)))
+        .collect();
+    let (window_start, window_end) = common_window(&schedules, after, min_minutes)
+        .ok_or_else(|| "No common free window left today".to_string())?;
+
+    let origins: Vec<Option<RoomCode>> = schedules
+        .iter()
+        .map(|busy| {
+            busy.iter()
+                .rev()
+                .find(|b| b.end <= window_start)
+                .and_then(|b| b.room.clone())
+        })
+        .collect();
+
+    let located = origins.iter().filter(|o| o.is_some()).count();
+    if located == 0 {
+        return Err("Nobody has a class before the window to start from".to_string());
+    }
+
+    // Walking seconds per spot, one entry per member with a known room
+    let mut costs: HashMap<String, (Spot, Vec<Option<u32>>)> = HashMap::new();
+    for (i, origin) in origins.iter().enumerate() {
+        let Some(room) = origin else { continue };
+        for (spot, seconds) in seconds_to_spots(room) {
+            costs
+                .entry(spot.code.clone())
+                .or_insert_with(|| (spot, vec![None; members.len()]))
+                .1[i] = Some(seconds);
+        }
+    }
+
+    let score = |walks: &[Option<u32>]| -> (u32, u32) {
+        let known = walks.iter().flatten();
+        let total: u32 = known.clone().sum();
+        let max = known.max().copied().unwrap_or(0);
+        match objective {
+            MeetingObjective::Total => (total, max),
+            MeetingObjective::Max => (max, total),
+        }
+    };
+
+    let (spot, walks) = costs
+        .into_values()
+        .filter(|(_, walks)| walks.iter().flatten().count() == located)
+        .min_by_key(|(spot, walks)| (score(walks), spot.code.clone()))
+        .ok_or_else(|| "No spot is reachable for everyone".to_string())?;
+
+    let total_seconds: u32 = walks.iter().flatten().sum();
+    let max_seconds = walks.iter().flatten().max().copied().unwrap_or(0);
+    let window_minutes = (window_end - window_start).num_minutes();
+
+    Ok(MeetingSuggestion {
+        window_start,
+        window_end,
+        spot,
+        total_seconds,
+        max_seconds,
+        remaining_minutes: window_minutes - (max_seconds as i64 + 59) / 60,
+        walks: members
+            .iter()
+            .zip(origins)
+            .zip(walks)
+            .map(|((member, from), seconds)| MemberWalk {
+                name: member.name.clone(),
+                from,
+                seconds,
+            })
+            .collect(),
+    })
+}
+
+#[tauri::command]
+pub fn suggest_meeting_point(
+    members: Vec<MeetingMember>,
+    day: u8,
+    after: String,
+    objective: Option<MeetingObjective>,
+    min_minutes: Option<i64>,
+) -> Result<MeetingSuggestion, String> {
+    let after = NaiveTime::parse_from_str(&after, "%H:%M")
+        .map_err(|e| format!("Invalid time '{}': {}", after, e))?;
+    suggest(
+        &members,
+        day,
+        after,
+        objective.unwrap_or_default(),
+        min_minutes.unwrap_or(DEFAULT_MIN_WINDOW_MINUTES),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{at, slot};
+
+    fn member(name: &str, classes: &[(u8, &str)]) -> MeetingMember {
+        MeetingMember {
+            name: name.to_string(),
+            time_table: classes.iter().map(|&(p, f)| slot(1, "t", p, f)).collect(),
+        }
+    }
+
+    /// Both free from 09:45 to 11:40, one leaving SJT and one TT
+    fn group() -> Vec<MeetingMember> {
+        vec![
+            member(
+                "Sana",
+                &[(1, "A-SJT-G15"), (2, "B-SJT-G15"), (5, "E-SJT-G15")],
+            ),
+            member("Rahul", &[(2, "B-TT-G12"), (5, "E-TT-G12")]),
+        ]
+    }
+
+    #[test]
+    fn window_skips_gaps_shorter_than_asked() {
+        let suggestion = suggest(&group(), 1, at(8, 0), MeetingObjective::Total, 20).unwrap();
+        assert_eq!(
+            (suggestion.window_start, suggestion.window_end),
+            (at(9, 45), at(11, 40))
+        );
+        let froms: Vec<_> = suggestion
+            .walks
+            .iter()
+            .map(|w| w.from.as_ref().unwrap().building.as_str())
+            .collect();
+        assert_eq!(froms, vec!["SJT", "TT"]);
+    }
+
+    #[test]
+    fn objective_changes_the_spot() {
+        let total = suggest(&group(), 1, at(8, 0), MeetingObjective::Total, 20).unwrap();
+        assert_eq!(total.spot.code, "SJT");
+        assert_eq!(total.walks[0].seconds, Some(0));
+
+        // The canteen between the blocks keeps the longest walk shortest
+        let fair = suggest(&group(), 1, at(8, 0), MeetingObjective::Max, 20).unwrap();
+        assert_eq!(fair.spot.code, "FOODYS");
+        assert!(fair.max_seconds < total.max_seconds);
+        assert!(fair.total_seconds > total.total_seconds);
+        assert_eq!(
+            fair.remaining_minutes,
+            115 - (fair.max_seconds as i64 + 59) / 60
+        );
+    }
+
+    #[test]
+    fn nobody_coming_from_class_is_an_error() {
+        let error = suggest(&group(), 2, at(8, 0), MeetingObjective::Total, 20).unwrap_err();
+        assert!(error.contains("Nobody"), "{}", error);
+        let error = suggest(&group(), 1, at(19, 30), MeetingObjective::Total, 20).unwrap_err();
+        assert!(error.contains("No common free window"), "{}", error);
+    }
+}
@@ -0,0 +1,213 @@
+//! The one timetable data model shared by parsing, scheduling, P2P and the
+//! share codecs, plus upgrades for JSON written by older versions.
+//!
+//! Versions:
+//! - 1: no `v` field. Covers the old parser output (`s` as a signed number,
+//!   no `h`/`q`) and the pre-P2P `Student` records (no `r`, `s`, `h`, `q`).
+//! - 2: `v` present, every field filled.
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+pub const SCHEMA_VERSION: u32 = 2;
+
+fn current_version() -> u32 {
+    SCHEMA_VERSION
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompactSlot {
+    pub d: u8,     // day (1-7)
+    pub s: String, // "t" or "l"
+    pub p: u8,     // period (1-12)
+    pub f: String, // original full text
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareData {
+    #[serde(default = "current_version")]
+    pub v: u32, // schema version
+    pub u: String, // username
+    #[serde(default)]
+    pub r: String, // registration number
+    #[serde(default)]
+    pub s: u32, // semester
+    #[serde(default)]
+    pub h: Vec<String>, // hobbies
+    #[serde(default)]
+    pub q: Vec<String>, // quote
+    #[serde(default)]
+    pub t: String, // timestamp
+    #[serde(default)]
+    pub o: Vec<CompactSlot>, // schedule slots
+}
+
+/// Semester as a non-negative number, whatever shape it was stored in
+fn upgrade_semester(value: Option<&Value>) -> Value {
+    let semester = match value {
+        Some(Value::Number(n)) => n.as_i64().unwrap_or(0),
+        Some(Value::String(s)) => s.trim().parse().unwrap_or(0),
+        _ => 0,
+    };
+    Value::from(semester.clamp(0, u32::MAX as i64))
+}
+
+/// Slot kinds were sometimes written as "TH"/"LO"/"theory"/"lab"; map those
+/// to "t" and "l" and leave anything else for validation to flag
+fn upgrade_slot(slot: &mut Map<String, Value>) {
+    let kind = match slot.get("s").and_then(Value::as_str) {
+        Some(s) => match s.trim().to_ascii_lowercase().as_str() {
+            "t" | "th" | "theory" => Some("t"),
+            "l" | "lo" | "lab" => Some("l"),
+            _ => None,
+        },
+        None => None,
+    };
+    if let Some(kind) = kind {
+        slot.insert("s".to_string(), Value::from(kind));
+    }
+    if !slot.contains_key("f") {
+        slot.insert("f".to_string(), Value::from(""));
+    }
+}
+
+fn upgrade_v1(data: &mut Map<String, Value>) {
+    for key in ["r", "t"] {
+        if !matches!(data.get(key), Some(Value::String(_))) {
+            data.insert(key.to_string(), Value::from(""));
+        }
+    }
+    for key in ["h", "q"] {
+        match data.get(key) {
+            Some(Value::Array(_)) => {}
+            // A single hobby or quote stored as a plain string
+            Some(Value::String(s)) if !s.is_empty() => {
+                let single = Value::Array(vec![Value::from(s.clone())]);
+                data.insert(key.to_string(), single);
+            }
+            _ => {
+                data.insert(key.to_string(), Value::Array(Vec::new()));
+            }
+        }
+    }
+    let semester = upgrade_semester(data.get("s"));
+    data.insert("s".to_string(), semester);
+
+    if let Some(Value::Array(slots)) = data.get_mut("o") {
+        for slot in slots.iter_mut() {
+            if let Value::Object(slot) = slot {
+                upgrade_slot(slot);
+            }
+        }
+    }
+}
+
+/// Read share data of any known version, upgrading it to the current one
+pub fn migrate_share_data(value: Value) -> Result<ShareData, String> {
+    let Value::Object(mut data) = value else {
+        return Err("Share data is not a JSON object".to_string());
+    };
+    let version = data.get("v").and_then(Value::as_u64).unwrap_or(1);
+
+    if version < 2 {
+        upgrade_v1(&mut data);
+    }
+    // Newer versions only ever add fields, which serde skips
+
+    let mut share_data: ShareData = serde_json::from_value(Value::Object(data))
+        .map_err(|e| format!("Failed to parse share data: {}", e))?;
+    share_data.v = SCHEMA_VERSION;
+    Ok(share_data)
+}
+
+/// `migrate_share_data` over each entry, for lists passed in by the frontend
+pub fn migrate_share_data_list(values: Vec<Value>) -> Result<Vec<ShareData>, String> {
+    values.into_iter().map(migrate_share_data).collect()
+}
+
+/// For `#[serde(deserialize_with)]` on optional share data that may have
+/// been stored by an older version
+pub fn deserialize_migrated<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<ShareData>, D::Error> {
+    Option::<Value>::deserialize(deserializer)?
+        .map(migrate_share_data)
+        .transpose()
+        .map_err(D::Error::custom)
+}
+
+/// `migrate_share_data` over raw bytes, for data received from peers
+pub fn share_data_from_slice(bytes: &[u8]) -> Result<ShareData, String> {
+    let value: Value =
+        serde_json::from_slice(bytes).map_err(|e| format!("Failed to parse share data: {}", e))?;
+    migrate_share_data(value)
+}
+
+/// Upgrade stored or received JSON so the frontend can save it back
+#[tauri::command]
+pub fn upgrade_share_data(data: Value) -> Result<ShareData, String> {
+    migrate_share_data(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn v1_data_is_filled_in() {
+        let data = migrate_share_data(json!({
+            "u": "Sana",
+            "s": "5",
+            "h": "chess",
+            "o": [{ "d": 1, "s": "TH", "p": 2 }],
+        }))
+        .unwrap();
+        assert_eq!(data.v, SCHEMA_VERSION);
+        assert_eq!(data.s, 5);
+        assert_eq!(data.h, vec!["chess"]);
+        assert!(data.r.is_empty() && data.q.is_empty());
+        assert_eq!(data.o[0].s, "t");
+        assert_eq!(data.o[0].f, "");
+    }
+
+    #[test]
+    fn only_known_slot_kinds_are_renamed() {
+        let kinds = |kinds: &[&str]| {
+            let slots: Vec<Value> = kinds
+                .iter()
+                .map(|s| json!({ "d": 1, "s": s, "p": 1, "f": "" }))
+                .collect();
+            migrate_share_data(json!({ "u": "Sana", "o": slots }))
+                .unwrap()
+                .o
+                .into_iter()
+                .map(|slot| slot.s)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            kinds(&["Theory", "th", "LO", "lab", "l", "t"]),
+            vec!["t", "t", "l", "l", "l", "t"]
+        );
+        assert_eq!(kinds(&["lecture", "x"]), vec!["lecture", "x"]);
+    }
+
+    #[test]
+    fn current_version_is_read_as_is() {
+        let data = migrate_share_data(json!({
+            "v": 2,
+            "u": "Sana",
+            "r": "23BCE0001",
+            "s": 5,
+            "h": [],
+            "q": [],
+            "t": "1",
+            "o": [{ "d": 1, "s": "lab", "p": 1, "f": "" }],
+        }))
+        .unwrap();
+        // Only v1 data is upgraded
+        assert_eq!(data.o[0].s, "lab");
+        assert!(migrate_share_data(json!([])).is_err());
+    }
+}
@@ -354,22 +354,6 @@ pub fn student_with_most_free_time(students: Vec<Student>) -> Result<StudentFree
     })
 }
 
-#[command]
-pub fn get_heatmap(students: Vec<Student>) -> Result<Vec<u32>, String> {
-    let mut heatmap = vec![0u32; 130];
-
-    for student in &students {
-        let (bitmap, _) = build_bitmap(&student.o);
-        for (i, &bit) in bitmap.iter().enumerate() {
-            if i < heatmap.len() {
-                heatmap[i] += bit as u32;
-            }
-        }
-    }
-
-    Ok(heatmap)
-}
-
 #[command]
 pub fn students_free_for(
     students: Vec<Student>,
@@ -1,14 +1,6 @@
 use chrono::NaiveTime;
-use serde::{Deserialize, Serialize};
-use tauri;
-
-#[derive(Debug, Deserialize)]
-pub struct CompactSlot {
-    d: u8,     // day (1-7)
-    s: String, // "t" or "l"
-    p: u8,     // period (1-12)
-    f: String, // original full text
-}
+
+pub use crate::model::CompactSlot;
 
 #[tauri::command]
 pub fn build_bitmap(schedule: Vec<CompactSlot>, target_day: u8) -> Vec<bool> {
@@ -32,16 +24,6 @@ pub fn build_kindmap(schedule: Vec<CompactSlot>, target_day: u8) -> Vec<bool> {
     kindmap
 }
 
-const THEORY_TIMES: [&str; 12] = [
-    "08:00", "08:55", "09:50", "10:45", "11:40", "12:35", "14:00", "14:55", "15:50", "16:45",
-    "17:40", "18:35",
-];
-
-const LAB_TIMES: [&str; 12] = [
-    "08:00", "08:50", "09:50", "10:40", "11:40", "12:30", "14:00", "14:50", "15:50", "16:40",
-    "17:40", "18:30",
-];
-
 const THEORY_PERIODS: [(&str, &str); 12] = [
     ("08:00", "08:50"),
     ("08:55", "09:45"),
@@ -72,6 +54,22 @@ const LAB_PERIODS: [(&str, &str); 12] = [
     ("18:30", "19:20"),
 ];
 
+/// Start and end of a 1-based period, using lab timings when `is_lab`
+pub(crate) fn period_bounds(period: u8, is_lab: bool) -> Option<(NaiveTime, NaiveTime)> {
+    if !(1..=12).contains(&period) {
+        return None;
+    }
+    let (start, end) = if is_lab {
+        LAB_PERIODS[period as usize - 1]
+    } else {
+        THEORY_PERIODS[period as usize - 1]
+    };
+    Some((
+        NaiveTime::parse_from_str(start, "%H:%M").ok()?,
+        NaiveTime::parse_from_str(end, "%H:%M").ok()?,
+    ))
+}
+
 #[tauri::command]
 pub fn currentbit(bitmap: [bool; 12], kindmap: [bool; 12]) -> Result<u8, String> {
     let current_time = chrono::Local::now().time();
@@ -260,39 +258,39 @@ pub fn get_free_status(
         }
         
         // If not in a specific period, free until end of day
-        return Some(FreeStatus {
+        Some(FreeStatus {
             is_busy: false,
             from: current_time,
             until: None,
             is_lunch: false,
-        });
+        })
     } else if next_free_time_str == "NO FREE TIME AVAILABLE" {
         // No more free time today
-        return Some(FreeStatus {
+        Some(FreeStatus {
             is_busy: true,
             from: current_time,
             until: None,
             is_lunch: false,
-        });
+        })
     } else {
         // User is busy, will be free at the time specified
         match NaiveTime::parse_from_str(&next_free_time_str, "%H:%M") {
             Ok(time) => {
-                return Some(FreeStatus {
+                Some(FreeStatus {
                     is_busy: true,
                     from: current_time,
                     until: Some(time),
                     is_lunch: time == lunch_start, // If next free time is lunch start
-                });
+                })
             },
             Err(_) => {
                 // Parse error - fall back to default
-                return Some(FreeStatus {
+                Some(FreeStatus {
                     is_busy: true,
                     from: current_time,
                     until: None,
                     is_lunch: false,
-                });
+                })
             }
         }
     }
@@ -321,7 +319,7 @@ pub fn new_get_free_status(
     // Check if we're in any class period
     let mut in_period = false;
     let mut current_period_index = 0;
-    let mut current_period_end = NaiveTime::from_hms(0, 0, 0);
+    let mut current_period_end = NaiveTime::from_hms_opt(0, 0, 0).unwrap();
     
     for i in 0..12 {
         let (start_str, end_str) = if kindmap[i] {
@@ -467,6 +465,8 @@ pub fn new_get_free_status(
     })
 }
 
+/// Raw slot text at a time. Prefer `location::locations_after`, which
+/// returns parsed rooms and covers free periods too.
 #[tauri::command]
 pub fn currently_at(time: &str, time_table: Vec<CompactSlot>, day: u8, is_end_time: bool) -> Option<String> {
     // Parse the current time
@@ -534,7 +534,7 @@ pub fn currently_at(time: &str, time_table: Vec<CompactSlot>, day: u8, is_end_ti
         // If no exact match for end time, find the next period that would start after this time
         if current_period.is_none() {
             let mut next_period_index = None;
-            let mut next_period_time = NaiveTime::from_hms(23, 59, 59); // Initialize with end of day
+            let mut next_period_time = NaiveTime::from_hms_opt(23, 59, 59).unwrap(); // Initialize with end of day
             
             for i in 0..12 {
                 let (theory_start, _) = THEORY_PERIODS[i];
@@ -563,10 +563,7 @@ pub fn currently_at(time: &str, time_table: Vec<CompactSlot>, day: u8, is_end_ti
     }
 
     // If we couldn't determine the current period, return None
-    let period = match current_period {
-        Some(p) => p,
-        None => return None,
-    };
+    let period = current_period?;
 
     // Look for a matching slot in the time_table with the same day and period
     for slot in time_table {
@@ -0,0 +1,565 @@
+//! Local notifications for class changes: "tell me when a friend is free"
+//! and "remind me before my next class".
+//!
+//! The frontend hands over timetables, watch rules and the academic calendar;
+//! any change recomputes the plan. On desktop a background task shows each
+//! alert when it is due. On mobile the app is suspended in the background,
+//! so the next `HORIZON_DAYS` of alerts are handed to the OS up front and
+//! re-planned whenever the app resumes.
+
+use chrono::{Datelike, Duration, Local, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+#[cfg(desktop)]
+use std::sync::Arc;
+use std::sync::Mutex;
+use tauri::async_runtime::JoinHandle;
+use tauri::plugin::PermissionState;
+use tauri::{AppHandle, State};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::history::person_key;
+use crate::location::{day_timeline, LocationInterval, Whereabouts};
+use crate::model::{migrate_share_data_list, CompactSlot, ShareData};
+
+/// How far ahead alerts are planned
+const HORIZON_DAYS: i64 = 2;
+/// iOS keeps at most 64 pending notifications per app
+const MAX_ALERTS: usize = 64;
+/// Longest the desktop timer sleeps before re-checking the clock, so a
+/// laptop waking from sleep does not fire stale alerts late
+#[cfg(desktop)]
+const RECHECK: std::time::Duration = std::time::Duration::from_secs(10 * 60);
+/// Alerts overdue by more than this when the timer wakes are dropped
+#[cfg(desktop)]
+const MAX_LATE: Duration = Duration::minutes(5);
+
+/// Days with no classes and days that follow another day's timetable
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AcademicCalendar {
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+    #[serde(default)]
+    pub day_orders: Vec<DayOrder>,
+    /// Classes only run between these dates when set
+    #[serde(default)]
+    pub starts: Option<NaiveDate>,
+    #[serde(default)]
+    pub ends: Option<NaiveDate>,
+}
+
+/// A date that runs the timetable of `day` (1 = Monday), e.g. a Saturday
+/// on Monday order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DayOrder {
+    pub date: NaiveDate,
+    pub day: u8,
+}
+
+impl AcademicCalendar {
+    /// Timetable day that runs on `date`, or `None` when there are no classes
+    pub fn timetable_day(&self, date: NaiveDate) -> Option<u8> {
+        if self.starts.is_some_and(|starts| date < starts)
+            || self.ends.is_some_and(|ends| date > ends)
+            || self.holidays.contains(&date)
+        {
+            return None;
+        }
+        match self
+            .day_orders
+            .iter()
+            .rev()
+            .find(|order| order.date == date)
+        {
+            Some(order) => Some(order.day),
+            None => Some(date.weekday().number_from_monday() as u8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WatchEvent {
+    /// Their last class before a break ends
+    Free,
+    /// Their first class after a break starts
+    Busy,
+}
+
+/// "Ping me `lead_minutes` before `friend` becomes free"
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchRule {
+    pub friend: String, // registration number, or username without one
+    pub event: WatchEvent,
+    #[serde(default)]
+    pub lead_minutes: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+struct NotificationConfig {
+    own: Vec<CompactSlot>,
+    class_reminder_minutes: Option<u32>,
+    friends: Vec<ShareData>,
+    rules: Vec<WatchRule>,
+    calendar: AcademicCalendar,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    OwnClass,
+    FriendFree,
+    FriendBusy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub id: i32,
+    pub at: NaiveDateTime,
+    pub kind: AlertKind,
+    pub person: Option<String>,
+    pub title: String,
+    pub body: String,
+}
+
+/// The slot text, e.g. "BMAT201L-AB3-206"
+fn class_text(interval: &LocationInterval) -> String {
+    match &interval.whereabouts {
+        Whereabouts::InClass { raw, .. } => raw.clone(),
+        _ => String::new(),
+    }
+}
+
+/// Id from the class an alert is about rather than its place in the plan,
+/// so a re-plan keeps pointing at the same pending OS notification
+fn alert_id(
+    date: NaiveDate,
+    interval: &LocationInterval,
+    kind: AlertKind,
+    person: Option<&str>,
+) -> i32 {
+    let key = format!(
+        "{}|{}|{}|{:?}|{}",
+        date,
+        interval.start,
+        class_text(interval),
+        kind,
+        person.unwrap_or_default()
+    );
+    let digest = Sha256::digest(key.as_bytes());
+    // Android takes ids as a Java int; keep them positive
+    i32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]) & i32::MAX
+}
+
+fn in_class(interval: Option<&LocationInterval>) -> bool {
+    matches!(
+        interval.map(|i| &i.whereabouts),
+        Some(Whereabouts::InClass { .. }) | Some(Whereabouts::Transit { .. })
+    )
+}
+
+fn own_alerts(config: &NotificationConfig, date: NaiveDate, day: u8, alerts: &mut Vec<Alert>) {
+    let Some(lead) = config.class_reminder_minutes else {
+        return;
+    };
+    for interval in day_timeline(&config.own, day) {
+        if !matches!(interval.whereabouts, Whereabouts::InClass { .. }) {
+            continue;
+        }
+        alerts.push(Alert {
+            id: alert_id(date, &interval, AlertKind::OwnClass, None),
+            at: date.and_time(interval.start) - Duration::minutes(lead as i64),
+            kind: AlertKind::OwnClass,
+            person: None,
+            title: format!("Class at {}", interval.start.format("%H:%M")),
+            body: class_text(&interval),
+        });
+    }
+}
+
+fn friend_alerts(
+    config: &NotificationConfig,
+    rule: &WatchRule,
+    date: NaiveDate,
+    day: u8,
+    alerts: &mut Vec<Alert>,
+) {
+    let wanted = rule.friend.trim().to_uppercase();
+    let Some(friend) = config.friends.iter().find(|f| person_key(f) == wanted) else {
+        return;
+    };
+    let lead = Duration::minutes(rule.lead_minutes as i64);
+    let timeline = day_timeline(&friend.o, day);
+
+    for (i, interval) in timeline.iter().enumerate() {
+        if !matches!(interval.whereabouts, Whereabouts::InClass { .. }) {
+            continue;
+        }
+        let (at, kind, title) = match rule.event {
+            // Back-to-back classes are not a break
+            WatchEvent::Free if !in_class(timeline.get(i + 1)) => (
+                date.and_time(interval.end),
+                AlertKind::FriendFree,
+                format!("{} is free at {}", friend.u, interval.end.format("%H:%M")),
+            ),
+            WatchEvent::Busy if i == 0 || !in_class(timeline.get(i - 1)) => (
+                date.and_time(interval.start),
+                AlertKind::FriendBusy,
+                format!(
+                    "{} has class at {}",
+                    friend.u,
+                    interval.start.format("%H:%M")
+                ),
+            ),
+            _ => continue,
+        };
+        let person = person_key(friend);
+        alerts.push(Alert {
+            id: alert_id(date, interval, kind, Some(&person)),
+            at: at - lead,
+            kind,
+            person: Some(person),
+            title,
+            body: class_text(interval),
+        });
+    }
+}
+
+/// Alerts due after `from`, soonest first, over the next `days` days
+fn plan_alerts(config: &NotificationConfig, from: NaiveDateTime, days: i64) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    for offset in 0..days {
+        let date = from.date() + Duration::days(offset);
+        let Some(day) = config.calendar.timetable_day(date) else {
+            continue;
+        };
+        own_alerts(config, date, day, &mut alerts);
+        for rule in &config.rules {
+            friend_alerts(config, rule, date, day, &mut alerts);
+        }
+    }
+
+    alerts.retain(|alert| alert.at > from);
+    alerts.sort_by_key(|alert| alert.at);
+    // Two rules on the same friend and event give one alert, the earliest
+    let mut ids = HashSet::new();
+    alerts.retain(|alert| ids.insert(alert.id));
+    alerts.truncate(MAX_ALERTS);
+    alerts
+}
+
+fn now() -> NaiveDateTime {
+    Local::now().naive_local()
+}
+
+#[derive(Default)]
+struct Scheduler {
+    config: NotificationConfig,
+    task: Option<JoinHandle<()>>,
+    /// Alerts up to here have been shown; kept across re-plans so a config
+    /// change neither repeats nor skips an alert
+    #[cfg(desktop)]
+    fired_until: Option<Arc<Mutex<NaiveDateTime>>>,
+    #[cfg(mobile)]
+    scheduled: Vec<i32>, // ids handed to the OS
+}
+
+#[derive(Default)]
+pub struct NotificationState(Mutex<Scheduler>);
+
+/// Split the alerts after `since` into those due by `now` and the rest
+#[cfg(desktop)]
+fn due_by(
+    config: &NotificationConfig,
+    since: NaiveDateTime,
+    now: NaiveDateTime,
+) -> (Vec<Alert>, Vec<Alert>) {
+    plan_alerts(config, since, HORIZON_DAYS)
+        .into_iter()
+        .partition(|alert| alert.at <= now)
+}
+
+#[cfg(desktop)]
+fn spawn_timer(
+    app: &AppHandle,
+    config: NotificationConfig,
+    fired_until: Arc<Mutex<NaiveDateTime>>,
+) -> JoinHandle<()> {
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            let now = now();
+            // Everything after the last wake is due, but not alerts missed
+            // while the machine was asleep
+            let since = (*fired_until.lock().unwrap()).max(now - MAX_LATE);
+            let (due, upcoming) = due_by(&config, since, now);
+            for alert in due {
+                if let Err(e) = app
+                    .notification()
+                    .builder()
+                    .id(alert.id)
+                    .title(&alert.title)
+                    .body(&alert.body)
+                    .show()
+                {
+                    eprintln!("Failed to show notification: {}", e);
+                }
+            }
+            {
+                let mut fired = fired_until.lock().unwrap();
+                *fired = (*fired).max(now);
+            }
+
+            let wait = upcoming
+                .first()
+                .and_then(|alert| (alert.at - now).to_std().ok())
+                .map_or(RECHECK, |wait| wait.min(RECHECK));
+            tokio::time::sleep(wait).await;
+        }
+    })
+}
+
+#[cfg(mobile)]
+fn schedule_with_os(app: &AppHandle, alerts: &[Alert]) -> Vec<i32> {
+    use chrono::TimeZone;
+    use tauri_plugin_notification::Schedule;
+
+    let mut scheduled = Vec::new();
+    for alert in alerts {
+        let Some(local) = Local.from_local_datetime(&alert.at).earliest() else {
+            continue;
+        };
+        let Ok(date) = time::OffsetDateTime::from_unix_timestamp(local.timestamp()) else {
+            continue;
+        };
+        let shown = app
+            .notification()
+            .builder()
+            .id(alert.id)
+            .title(&alert.title)
+            .body(&alert.body)
+            .schedule(Schedule::At {
+                date,
+                repeating: false,
+                allow_while_idle: true,
+            })
+            .show();
+        match shown {
+            Ok(()) => scheduled.push(alert.id),
+            Err(e) => eprintln!("Failed to schedule notification: {}", e),
+        }
+    }
+    scheduled
+}
+
+/// Throw away the current plan and build a new one from the config
+fn reschedule(app: &AppHandle, scheduler: &mut Scheduler) -> Vec<Alert> {
+    if let Some(task) = scheduler.task.take() {
+        task.abort();
+    }
+    let alerts = plan_alerts(&scheduler.config, now(), HORIZON_DAYS);
+
+    #[cfg(desktop)]
+    {
+        let fired_until = scheduler
+            .fired_until
+            .get_or_insert_with(|| Arc::new(Mutex::new(now())))
+            .clone();
+        scheduler.task = Some(spawn_timer(app, scheduler.config.clone(), fired_until));
+    }
+    #[cfg(mobile)]
+    {
+        let stale = std::mem::take(&mut scheduler.scheduled);
+        if !stale.is_empty() {
+            if let Err(e) = app.notification().cancel(stale) {
+                eprintln!("Failed to cancel notifications: {}", e);
+            }
+        }
+        scheduler.scheduled = schedule_with_os(app, &alerts);
+    }
+    alerts
+}
+
+/// Re-plan after the app comes back to the foreground, since mobile only
+/// has `HORIZON_DAYS` of alerts queued with the OS
+#[cfg(mobile)]
+pub(crate) fn refresh(app: &AppHandle) {
+    use tauri::Manager;
+
+    let state = app.state::<NotificationState>();
+    let mut scheduler = state.0.lock().unwrap();
+    reschedule(app, &mut scheduler);
+}
+
+/// Call whenever your own timetable or a friend's changes
+#[tauri::command]
+pub fn set_notification_timetables(
+    app_handle: AppHandle,
+    state: State<'_, NotificationState>,
+    own: Vec<CompactSlot>,
+    friends: Vec<Value>,
+) -> Result<Vec<Alert>, String> {
+    let friends = migrate_share_data_list(friends)?;
+    let mut scheduler = state.0.lock().unwrap();
+    scheduler.config.own = own;
+    scheduler.config.friends = friends;
+    Ok(reschedule(&app_handle, &mut scheduler))
+}
+
+/// Replace the watch rules; `class_reminder_minutes` of `None` turns off
+/// reminders for your own classes
+#[tauri::command]
+pub fn set_watch_rules(
+    app_handle: AppHandle,
+    state: State<'_, NotificationState>,
+    rules: Vec<WatchRule>,
+    class_reminder_minutes: Option<u32>,
+) -> Vec<Alert> {
+    let mut scheduler = state.0.lock().unwrap();
+    scheduler.config.rules = rules;
+    scheduler.config.class_reminder_minutes = class_reminder_minutes;
+    reschedule(&app_handle, &mut scheduler)
+}
+
+#[tauri::command]
+pub fn set_academic_calendar(
+    app_handle: AppHandle,
+    state: State<'_, NotificationState>,
+    calendar: AcademicCalendar,
+) -> Vec<Alert> {
+    let mut scheduler = state.0.lock().unwrap();
+    scheduler.config.calendar = calendar;
+    reschedule(&app_handle, &mut scheduler)
+}
+
+#[tauri::command]
+pub fn upcoming_alerts(state: State<'_, NotificationState>) -> Vec<Alert> {
+    plan_alerts(&state.0.lock().unwrap().config, now(), HORIZON_DAYS)
+}
+
+/// Ask for permission to notify if not yet decided; returns whether granted
+#[tauri::command]
+pub fn request_notification_permission(app_handle: AppHandle) -> Result<bool, String> {
+    let notification = app_handle.notification();
+    let mut permission = notification
+        .permission_state()
+        .map_err(|e| format!("Failed to read notification permission: {}", e))?;
+    if !matches!(
+        permission,
+        PermissionState::Granted | PermissionState::Denied
+    ) {
+        permission = notification
+            .request_permission()
+            .map_err(|e| format!("Failed to request notification permission: {}", e))?;
+    }
+    Ok(matches!(permission, PermissionState::Granted))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{share_data, slot};
+
+    fn friend(name: &str, reg: &str) -> ShareData {
+        ShareData {
+            r: reg.to_string(),
+            ..share_data(name, vec![slot(1, "t", 1, "BCSE101L-AB1-301")])
+        }
+    }
+
+    fn monday_at(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2025, 6, 16)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn config() -> NotificationConfig {
+        let rule = |reg: &str| WatchRule {
+            friend: reg.to_string(),
+            event: WatchEvent::Free,
+            lead_minutes: 0,
+        };
+        NotificationConfig {
+            friends: vec![friend("Sana", "23BCE0001"), friend("Rahul", "23BCE0002")],
+            rules: vec![rule("23bce0001"), rule("23BCE0002")],
+            ..NotificationConfig::default()
+        }
+    }
+
+    #[test]
+    fn calendar_skips_holidays_and_follows_day_orders() {
+        let saturday = NaiveDate::from_ymd_opt(2025, 6, 21).unwrap();
+        let monday = NaiveDate::from_ymd_opt(2025, 6, 16).unwrap();
+        let calendar = AcademicCalendar {
+            holidays: vec![monday],
+            day_orders: vec![DayOrder {
+                date: saturday,
+                day: 1,
+            }],
+            ..AcademicCalendar::default()
+        };
+        assert_eq!(calendar.timetable_day(monday), None);
+        assert_eq!(calendar.timetable_day(saturday), Some(1));
+        assert_eq!(calendar.timetable_day(monday.succ_opt().unwrap()), Some(2));
+    }
+
+    #[test]
+    fn alerts_at_the_same_time_are_all_due() {
+        let (due, _) = due_by(&config(), monday_at(8, 45), monday_at(8, 50));
+        let mut people: Vec<_> = due.iter().filter_map(|a| a.person.clone()).collect();
+        people.sort();
+        assert_eq!(people, vec!["23BCE0001", "23BCE0002"]);
+        assert!(due.iter().all(|a| a.at == monday_at(8, 50)));
+    }
+
+    #[test]
+    fn fired_alerts_are_not_repeated() {
+        let (due, upcoming) = due_by(&config(), monday_at(8, 50), monday_at(9, 30));
+        assert!(due.is_empty());
+        assert!(upcoming.iter().all(|a| a.at > monday_at(9, 30)));
+    }
+
+    #[test]
+    fn own_reminders_lead_the_class() {
+        let config = NotificationConfig {
+            own: friend("Me", "23BCE0003").o,
+            class_reminder_minutes: Some(10),
+            ..NotificationConfig::default()
+        };
+        let alerts = plan_alerts(&config, monday_at(7, 0), 1);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].kind, AlertKind::OwnClass);
+        assert_eq!(alerts[0].at, monday_at(7, 50));
+    }
+
+    #[test]
+    fn alert_ids_survive_a_re_plan() {
+        let mut config = config();
+        let before = plan_alerts(&config, monday_at(7, 0), 1);
+        assert_ne!(before[0].id, before[1].id);
+
+        // Sana's alert moves earlier and Rahul's is dropped
+        config.rules[0].lead_minutes = 10;
+        config.rules.remove(1);
+        let after = plan_alerts(&config, monday_at(8, 0), 1);
+        assert_eq!(after.len(), 1);
+        let sana = before.iter().find(|a| a.person == after[0].person).unwrap();
+        assert_eq!(after[0].id, sana.id);
+        assert_eq!(after[0].at, monday_at(8, 40));
+    }
+
+    #[test]
+    fn repeated_rules_give_one_alert() {
+        let mut config = config();
+        config.rules.push(WatchRule {
+            lead_minutes: 5,
+            ..config.rules[0].clone()
+        });
+        let alerts = plan_alerts(&config, monday_at(7, 0), 1);
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].at, monday_at(8, 45));
+    }
+}
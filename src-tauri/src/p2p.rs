@@ -4,47 +4,68 @@ use iroh::{
     discovery::{Discovery, EndpointData, UserData}, // MODIFIED: Correct imports
     endpoint::Connection,
     protocol::{AcceptError, ProtocolHandler, Router},
-    Endpoint, EndpointAddr, PublicKey,
+    Endpoint, EndpointAddr, PublicKey, RelayMode, SecretKey,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::convert::TryFrom; // MODIFIED: Added for UserData
 use std::future::Future;
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tauri::{Emitter, State};
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, Mutex, RwLock};
 use tokio::task::JoinHandle;
 
-const ALPN: &[u8] = b"vfriend/request";
+use crate::advert::Advertisement;
+use crate::forward::{
+    ForwardCache, SealedBundle, SyncProtocolHandler, SyncRequest, SyncResponse,
+    MAX_SYNC_MESSAGE_LEN, SYNC_ALPN,
+};
+use crate::model::share_data_from_slice;
+use crate::proximity::{NearbySighting, ProximityTracker};
+use crate::registry::{FriendRecord, FriendRegistry};
+use crate::ticket::FriendTicket;
+
+pub const ALPN: &[u8] = b"vfriend/request";
+
+/// How long a freshly minted invite stays valid
+const INVITE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Peers not re-announced within this window are considered gone
+const PEER_TTL: Duration = Duration::from_secs(2 * 60);
+const PEER_SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Give up on an offline or stalled friend quickly when syncing; covers the
+/// whole exchange, not just connecting
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
 
 // ============================================================================
 // Public Types for Tauri Frontend (matches TypeScript interface)
 // ============================================================================
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CompactSlot {
-    pub d: u8,     // day (1-7)
-    pub s: String, // "t" or "l"
-    pub p: u8,     // period (1-12)
-    pub f: String, // original full text
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ShareData {
-    pub u: String,           // username
-    pub r: String,           // registration number
-    pub s: u32,              // semester
-    pub h: Vec<String>,      // hobbies
-    pub q: Vec<String>,      // quote
-    pub t: String,           // timestamp
-    pub o: Vec<CompactSlot>, // schedule slots
-}
+pub use crate::model::{CompactSlot, ShareData};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct DiscoveredPeer {
     pub endpoint_id: String,
     pub name: String,
-    pub timestamp: u64,
+    pub semester: Option<u32>,
+    pub app_version: Option<String>,
+    pub profile_hash: Option<String>,
+    pub compatible: bool, // speaks our protocol version
+    pub timestamp: u64,   // last seen
+    pub first_seen: u64,  // first seen in this discovery session
+    pub is_friend: bool,
+}
+
+/// Outcome of pushing our share data to every friend
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PublishReport {
+    pub delivered: Vec<String>, // friends reached directly
+    pub forwarded: Vec<String>, // left with at least one mutual friend
+    pub failed: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,6 +80,8 @@ pub struct IncomingRequest {
 #[serde(tag = "type")]
 pub enum FriendEvent {
     PeerDiscovered { peer: DiscoveredPeer },
+    PeerLost { endpoint_id: String },
+    FriendNearby { sighting: NearbySighting },
     IncomingRequest { request: IncomingRequest },
     RequestAccepted { share_data: ShareData },
     RequestRejected { reason: String },
@@ -66,6 +89,54 @@ pub enum FriendEvent {
     Error { message: String },
 }
 
+// ============================================================================
+// Event Sink
+// ============================================================================
+
+/// Where the service reports what happens on the network. The Tauri app
+/// forwards events to the webview; the CLI and tests use a channel.
+pub trait EventSink: Send + Sync + 'static {
+    fn emit(&self, event: FriendEvent);
+}
+
+impl EventSink for mpsc::UnboundedSender<FriendEvent> {
+    fn emit(&self, event: FriendEvent) {
+        let _ = self.send(event);
+    }
+}
+
+/// Shared handle to the current sink; events are dropped until one is set
+#[derive(Clone, Default)]
+pub(crate) struct EventHub {
+    sink: Arc<std::sync::RwLock<Option<Arc<dyn EventSink>>>>,
+}
+
+impl EventHub {
+    fn set(&self, sink: Arc<dyn EventSink>) {
+        *self.sink.write().unwrap() = Some(sink);
+    }
+
+    fn is_set(&self) -> bool {
+        self.sink.read().unwrap().is_some()
+    }
+
+    pub(crate) fn emit(&self, event: FriendEvent) {
+        // Clone out so the sink runs without holding the lock
+        let sink = self.sink.read().unwrap().clone();
+        if let Some(sink) = sink {
+            sink.emit(event);
+        }
+    }
+}
+
+impl std::fmt::Debug for EventHub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventHub")
+            .field("is_set", &self.is_set())
+            .finish()
+    }
+}
+
 // ============================================================================
 // Internal Protocol Messages
 // ============================================================================
@@ -74,6 +145,8 @@ pub enum FriendEvent {
 struct FriendRequestMessage {
     from: String,
     name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    invite: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -85,14 +158,49 @@ struct FriendResponseMessage {
 // Main Service State
 // ============================================================================
 
+/// How the service endpoint is bound
+#[derive(Debug, Clone, Default)]
+pub struct ServiceOptions {
+    /// Fixed identity; a fresh one is generated when `None`
+    pub secret_key: Option<SecretKey>,
+    /// No relays and no DNS discovery: only direct addresses work
+    pub local_only: bool,
+    /// Neither advertise nor discover peers on the local network
+    pub disable_mdns: bool,
+}
+
+/// The P2P friend exchange. All methods take `&self` and lock only the
+/// piece of state they touch, so the service can be shared behind an `Arc`
+/// and long network operations never block unrelated calls.
 pub struct FriendExchangeService {
     endpoint: Endpoint,
-    mdns: MdnsDiscovery,
-    router: Option<Router>,
-    event_tx: Arc<Mutex<Option<mpsc::UnboundedSender<FriendEvent>>>>,
+    mdns: Option<MdnsDiscovery>,
+    router: Mutex<Option<Router>>,
+    events: EventHub,
     pending_requests: Arc<RwLock<Vec<PendingRequest>>>,
     my_share_data: Arc<RwLock<Option<ShareData>>>,
     discovery_task: Arc<Mutex<Option<JoinHandle<()>>>>,
+    invites: Arc<RwLock<HashMap<String, Instant>>>, // invite secret -> expiry
+    peers: Arc<RwLock<HashMap<String, DiscoveredPeer>>>,
+    friends: Arc<RwLock<FriendRegistry>>,
+    proximity: Arc<RwLock<ProximityTracker>>,
+    forward_cache: Arc<RwLock<ForwardCache>>,
+    options: ServiceOptions,
+    discovery_paused: AtomicBool,
+}
+
+/// Which parts of the service are currently running
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ServiceStatus {
+    pub initialized: bool,
+    pub endpoint_id: Option<String>,
+    pub accepting: bool,
+    pub discovering: bool,
+    pub discovery_paused: bool,
+    pub mdns: bool,
+    pub has_share_data: bool,
+    pub pending_requests: usize,
+    pub closed: bool,
 }
 
 #[derive(Debug)]
@@ -110,110 +218,250 @@ struct PendingRequest {
 impl FriendExchangeService {
     /// Initialize the service and start listening
     pub async fn new() -> Result<Self, String> {
-        let endpoint = Endpoint::bind()
+        Self::with_options(ServiceOptions::default()).await
+    }
+
+    /// Initialize with a fixed identity so the endpoint ID survives restarts
+    pub async fn with_secret_key(secret_key: SecretKey) -> Result<Self, String> {
+        Self::with_options(ServiceOptions {
+            secret_key: Some(secret_key),
+            ..ServiceOptions::default()
+        })
+        .await
+    }
+
+    pub async fn with_options(mut options: ServiceOptions) -> Result<Self, String> {
+        let mut builder = Endpoint::builder();
+        if options.local_only {
+            builder = builder.relay_mode(RelayMode::Disabled).clear_discovery();
+        }
+        if let Some(secret_key) = options.secret_key.clone() {
+            builder = builder.secret_key(secret_key);
+        }
+
+        let endpoint = builder
+            .bind()
             .await
             .map_err(|e| format!("Failed to bind endpoint: {}", e))?;
 
-        let endpoint_id = endpoint.id();
-        let mdns = MdnsDiscovery::builder()
-            .build(endpoint_id)
-            .map_err(|e| format!("Failed to create mDNS discovery: {}", e))?;
-
-        endpoint.discovery().add(mdns.clone());
+        let mdns = if options.disable_mdns {
+            None
+        } else {
+            let mdns = MdnsDiscovery::builder()
+                .build(endpoint.id())
+                .map_err(|e| format!("Failed to create mDNS discovery: {}", e))?;
+            endpoint.discovery().add(mdns.clone());
+            Some(mdns)
+        };
+        // Remember the identity so a restart keeps the same endpoint ID
+        options.secret_key = Some(endpoint.secret_key().clone());
 
         Ok(Self {
             endpoint,
             mdns,
-            router: None,
-            event_tx: Arc::new(Mutex::new(None)),
+            router: Mutex::new(None),
+            events: EventHub::default(),
             pending_requests: Arc::new(RwLock::new(Vec::new())),
             my_share_data: Arc::new(RwLock::new(None)),
             discovery_task: Arc::new(Mutex::new(None)),
+            invites: Arc::new(RwLock::new(HashMap::new())),
+            peers: Arc::new(RwLock::new(HashMap::new())),
+            friends: Arc::new(RwLock::new(FriendRegistry::new())),
+            proximity: Arc::new(RwLock::new(ProximityTracker::new())),
+            forward_cache: Arc::new(RwLock::new(ForwardCache::new())),
+            options,
+            discovery_paused: AtomicBool::new(false),
         })
     }
 
+    /// Shut this instance down and bind a fresh endpoint with the same
+    /// identity. Share data, friends, invites and cached bundles carry over;
+    /// the router and discovery come back up if they were running.
+    pub async fn restart(&self) -> Result<Self, String> {
+        let was_accepting = self.router.lock().await.is_some();
+        let was_discovering = self.is_discovering().await;
+        let was_paused = self.discovery_paused.load(Ordering::SeqCst);
+        self.shutdown().await?;
+
+        let mut service = Self::with_options(self.options.clone()).await?;
+        service.events = self.events.clone();
+        service.my_share_data = self.my_share_data.clone();
+        service.invites = self.invites.clone();
+        service.friends = self.friends.clone();
+        service.proximity = self.proximity.clone();
+        service.forward_cache = self.forward_cache.clone();
+
+        let share_data = service.my_share_data.read().await.clone();
+        if let Some(share_data) = share_data {
+            service.set_share_data(share_data).await;
+        }
+        if was_accepting {
+            *service.router.lock().await = Some(service.spawn_router());
+        }
+        if was_discovering {
+            service.start_discovery().await;
+        }
+        service.discovery_paused.store(was_paused, Ordering::SeqCst);
+        Ok(service)
+    }
+
     /// Get your own endpoint ID
     pub fn get_endpoint_id(&self) -> String {
         self.endpoint.id().to_string()
     }
 
-    // MODIFIED: This now correctly publishes the username
-    /// Set your share data
+    /// Local sockets the endpoint listens on
+    pub fn bound_sockets(&self) -> Vec<SocketAddr> {
+        self.endpoint.bound_sockets()
+    }
+
+    /// Set your share data and advertise a compact summary of it over mDNS
     pub async fn set_share_data(&self, share_data: ShareData) {
-        // MODIFIED: Create UserData using TryFrom<String>
-        let user_data = UserData::try_from(share_data.u.clone())
-            .unwrap_or_else(|e| {
-                // Handle case where username is too long
-                eprintln!("Username '{}' is too long for mDNS, using empty string. Error: {}", share_data.u, e);
-                UserData::try_from("".to_string()).unwrap() // Guaranteed to work
-            });
+        let advert = Advertisement::from_share_data(&share_data).encode();
+        let user_data = UserData::try_from(advert).unwrap_or_else(|e| {
+            // encode() keeps within the limit, so this is only a safety net
+            eprintln!(
+                "Advertisement too long for mDNS, using empty string. Error: {}",
+                e
+            );
+            UserData::try_from("".to_string()).unwrap() // Guaranteed to work
+        });
 
         // Create default EndpointData and set the user_data
         let mut endpoint_data = EndpointData::default();
         endpoint_data.set_user_data(Some(user_data));
 
         // MODIFIED: This now works because the Discovery trait is in scope
-        self.mdns.publish(&endpoint_data);
+        if let Some(mdns) = &self.mdns {
+            mdns.publish(&endpoint_data);
+        }
 
         // Store the data locally
         *self.my_share_data.write().await = Some(share_data);
     }
 
-    /// Start the service (begins accepting connections)
-    pub async fn start(
-        &mut self,
-        event_tx: mpsc::UnboundedSender<FriendEvent>,
-    ) -> Result<(), String> {
-        *self.event_tx.lock().await = Some(event_tx.clone());
+    /// Start the service (begins accepting connections). Calling it again
+    /// only swaps the event sink; the running router is kept.
+    pub async fn start(&self, sink: impl EventSink) -> Result<(), String> {
+        if self.endpoint.is_closed() {
+            return Err("Service has been shut down".to_string());
+        }
+        self.events.set(Arc::new(sink));
+
+        let mut router = self.router.lock().await;
+        if router.is_none() {
+            *router = Some(self.spawn_router());
+        }
+        Ok(())
+    }
 
+    fn spawn_router(&self) -> Router {
         let protocol = FriendProtocolHandler {
-            event_tx: self.event_tx.clone(),
+            events: self.events.clone(),
             pending_requests: self.pending_requests.clone(),
+            my_share_data: self.my_share_data.clone(),
+            invites: self.invites.clone(),
+            friends: self.friends.clone(),
+        };
+
+        let sync_protocol = SyncProtocolHandler {
+            secret_key: self.endpoint.secret_key().clone(),
+            events: self.events.clone(),
+            friends: self.friends.clone(),
+            cache: self.forward_cache.clone(),
         };
 
-        let router = Router::builder(self.endpoint.clone())
+        Router::builder(self.endpoint.clone())
             .accept(ALPN, protocol)
-            .spawn();
+            .accept(SYNC_ALPN, sync_protocol)
+            .spawn()
+    }
 
-        self.router = Some(router);
-        Ok(())
+    /// Snapshot of what is running, for the settings/debug screen
+    pub async fn status(&self) -> ServiceStatus {
+        ServiceStatus {
+            initialized: true,
+            endpoint_id: Some(self.get_endpoint_id()),
+            accepting: self.router.lock().await.is_some(),
+            discovering: self.is_discovering().await,
+            discovery_paused: self.discovery_paused.load(Ordering::SeqCst),
+            mdns: self.mdns.is_some(),
+            has_share_data: self.my_share_data.read().await.is_some(),
+            pending_requests: self.pending_requests.read().await.len(),
+            closed: self.endpoint.is_closed(),
+        }
     }
 
     /// Start discovering peers
     pub async fn start_discovery(&self) {
-        let event_tx = match self.event_tx.lock().await.as_ref() {
-            Some(tx) => tx.clone(),
-            None => {
-                eprintln!("Discovery started before service event loop.");
-                return;
-            }
+        if !self.events.is_set() {
+            eprintln!("Discovery started before service event loop.");
+            return;
+        }
+        let Some(mdns) = &self.mdns else {
+            eprintln!("Discovery started with mDNS disabled.");
+            return;
         };
+        let events = self.events.clone();
 
-        let mut stream = self.mdns.subscribe().await;
+        let mut stream = mdns.subscribe().await;
         let endpoint_id = self.endpoint.id();
+        let peers = self.peers.clone();
+        let friends = self.friends.clone();
+        let proximity = self.proximity.clone();
 
         let new_handle = tokio::spawn(async move {
-            while let Some(event) = stream.next().await {
-                if let DiscoveryEvent::Discovered { endpoint_info, .. } = event {
-                    let other = endpoint_info.endpoint_id;
-                    if other != endpoint_id {
-                        // MODIFIED: Correctly access the user_data via AsRef<str>
-                        let name = endpoint_info
-                            .data
-                            .user_data() // This returns Option<&UserData>
-                            .map(|user_data| user_data.as_ref()) // This returns &str
-                            .map(|s| s.to_string()) // Convert &str to String
-                            .unwrap_or_else(|| "Unknown".to_string()); // Default if not found
-
-                        let peer = DiscoveredPeer {
-                            endpoint_id: other.to_string(),
-                            name, // Use the extracted name
-                            timestamp: std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap()
-                                .as_secs(),
-                        };
-                        let _ = event_tx.send(FriendEvent::PeerDiscovered { peer });
+            let mut sweep = tokio::time::interval(PEER_SWEEP_INTERVAL);
+            loop {
+                tokio::select! {
+                    event = stream.next() => {
+                        let Some(event) = event else { break };
+                        match event {
+                            DiscoveryEvent::Discovered { endpoint_info, .. } => {
+                                let other = endpoint_info.endpoint_id;
+                                if other == endpoint_id {
+                                    continue;
+                                }
+
+                                let advert = endpoint_info
+                                    .data
+                                    .user_data()
+                                    .map(|user_data| Advertisement::parse(user_data.as_ref()))
+                                    .unwrap_or_else(|| Advertisement::parse("Unknown"));
+
+                                let other = other.to_string();
+                                let is_friend = friends.read().await.is_friend(&other);
+                                let is_new = !peers.read().await.contains_key(&other);
+
+                                // A friend appearing on our network is physically nearby
+                                if is_friend && is_new {
+                                    let sighting = proximity.write().await.observe(
+                                        &other,
+                                        &advert.name,
+                                        endpoint_info.data.ip_addrs(),
+                                    );
+                                    if let Some(sighting) = sighting {
+                                        events.emit(FriendEvent::FriendNearby { sighting });
+                                    }
+                                }
+
+                                let changed = upsert_peer(&peers, other, advert, is_friend).await;
+                                if let Some(peer) = changed {
+                                    events.emit(FriendEvent::PeerDiscovered { peer });
+                                }
+                            }
+                            DiscoveryEvent::Expired { endpoint_id: other } => {
+                                let other = other.to_string();
+                                if peers.write().await.remove(&other).is_some() {
+                                    events.emit(FriendEvent::PeerLost { endpoint_id: other });
+                                }
+                            }
+                        }
+                    }
+                    _ = sweep.tick() => {
+                        for endpoint_id in prune_stale_peers(&peers).await {
+                            events.emit(FriendEvent::PeerLost { endpoint_id });
+                        }
                     }
                 }
             }
@@ -232,6 +480,92 @@ impl FriendExchangeService {
         if let Some(handle) = task_handle_guard.take() {
             handle.abort();
         }
+        self.discovery_paused.store(false, Ordering::SeqCst);
+        self.peers.write().await.clear();
+    }
+
+    async fn is_discovering(&self) -> bool {
+        match self.discovery_task.lock().await.as_ref() {
+            Some(handle) => !handle.is_finished(),
+            None => false,
+        }
+    }
+
+    /// Stop discovery while the app is in the background, remembering
+    /// whether it should come back in `resume_discovery`
+    pub async fn pause_discovery(&self) {
+        if self.is_discovering().await {
+            self.stop_discovery().await;
+            self.discovery_paused.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Restart discovery if `pause_discovery` stopped it
+    pub async fn resume_discovery(&self) {
+        if self.discovery_paused.swap(false, Ordering::SeqCst) {
+            self.start_discovery().await;
+        }
+    }
+
+    /// Peers currently visible on the local network, most recently seen first
+    pub async fn list_nearby_peers(&self) -> Vec<DiscoveredPeer> {
+        prune_stale_peers(&self.peers).await;
+
+        let friends = self.friends.read().await;
+        let mut peers: Vec<DiscoveredPeer> = self
+            .peers
+            .read()
+            .await
+            .values()
+            .cloned()
+            .map(|mut peer| {
+                peer.is_friend = friends.is_friend(&peer.endpoint_id);
+                peer
+            })
+            .collect();
+        peers.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        peers
+    }
+
+    /// Turn proximity detection on or off for the whole service
+    pub async fn set_proximity_enabled(&self, enabled: bool) {
+        self.proximity.write().await.set_enabled(enabled);
+    }
+
+    /// Opt a friend in or out of "nearby" notifications
+    pub async fn set_nearby_watch(&self, endpoint_id: String, watched: bool) {
+        self.proximity
+            .write()
+            .await
+            .set_watched(endpoint_id, watched);
+    }
+
+    pub async fn nearby_history(&self) -> Vec<NearbySighting> {
+        self.proximity.read().await.history()
+    }
+
+    /// Seed the friend registry with friends known to the frontend
+    pub async fn set_known_friends(&self, friends: Vec<FriendRecord>) {
+        self.friends.write().await.replace_all(friends);
+    }
+
+    pub async fn known_friends(&self) -> Vec<FriendRecord> {
+        self.friends.read().await.records()
+    }
+
+    /// Create a connection ticket for QR codes and deep links. The embedded
+    /// invite is single-use and expires after `INVITE_TTL`; a request carrying
+    /// it is accepted without asking.
+    pub async fn create_ticket(&self) -> FriendTicket {
+        let invite = FriendTicket::generate_invite();
+
+        let mut invites = self.invites.write().await;
+        let now = Instant::now();
+        invites.retain(|_, expires_at| *expires_at > now);
+        invites.insert(invite.clone(), now + INVITE_TTL);
+        drop(invites);
+
+        FriendTicket::new(&self.endpoint.addr(), invite)
     }
 
     /// Send a friend request to a discovered peer
@@ -243,7 +577,28 @@ impl FriendExchangeService {
         let endpoint_id = PublicKey::from_str(&peer_endpoint_id)
             .map_err(|e| format!("Invalid endpoint ID: {}", e))?;
 
-        let addr = EndpointAddr::from(endpoint_id);
+        self.send_friend_request_to(EndpointAddr::from(endpoint_id), None, my_share_data)
+            .await
+    }
+
+    /// Send a friend request to the owner of a scanned ticket
+    pub async fn send_friend_request_with_ticket(
+        &self,
+        ticket: &str,
+        my_share_data: ShareData,
+    ) -> Result<ShareData, String> {
+        let ticket = FriendTicket::decode(ticket)?;
+        let addr = ticket.endpoint_addr()?;
+        self.send_friend_request_to(addr, Some(ticket.k), my_share_data)
+            .await
+    }
+
+    async fn send_friend_request_to(
+        &self,
+        addr: EndpointAddr,
+        invite: Option<String>,
+        my_share_data: ShareData,
+    ) -> Result<ShareData, String> {
         let conn = self
             .endpoint
             .connect(addr, ALPN)
@@ -259,6 +614,7 @@ impl FriendExchangeService {
         let req = FriendRequestMessage {
             from: my_share_data.r.clone(),
             name: my_share_data.u.clone(),
+            invite,
         };
         let data =
             serde_json::to_vec(&req).map_err(|e| format!("Failed to serialize request: {}", e))?;
@@ -304,18 +660,178 @@ impl FriendExchangeService {
             .read_to_end(100000)
             .await
             .map_err(|e| format!("Failed to read their data: {}", e))?;
-        let their_share_data: ShareData = serde_json::from_slice(&their_data_bytes)
+        let their_share_data = share_data_from_slice(&their_data_bytes)
             .map_err(|e| format!("Failed to parse their data: {}", e))?;
 
         conn.close(0u32.into(), b"bye!");
 
-        if let Some(tx) = self.event_tx.lock().await.as_ref() {
-            let _ = tx.send(FriendEvent::DataReceived {
-                share_data: their_share_data.clone(),
+        self.friends
+            .write()
+            .await
+            .record_exchange(conn.remote_id().to_string(), their_share_data.clone());
+
+        self.events.emit(FriendEvent::DataReceived {
+            share_data: their_share_data.clone(),
+        });
+
+        Ok(their_share_data)
+    }
+
+    /// Push our current share data to every friend. Friends that cannot be
+    /// reached get an encrypted bundle left with the friends that can.
+    pub async fn publish_share_update(&self) -> Result<PublishReport, String> {
+        let my_share_data = self
+            .my_share_data
+            .read()
+            .await
+            .clone()
+            .ok_or("Share data not set")?;
+        let friends = self.friends.read().await.endpoint_ids();
+        let secret_key = self.endpoint.secret_key();
+
+        let mut report = PublishReport::default();
+        let mut undelivered = Vec::new();
+        for friend in friends {
+            let Ok(bundle) = SealedBundle::seal(secret_key, &friend, &my_share_data) else {
+                report.failed.push(friend);
+                continue;
+            };
+            let request = SyncRequest::Deliver {
+                bundle: bundle.clone(),
+            };
+            match self.sync_request(&friend, &request).await {
+                Ok(_) => report.delivered.push(friend),
+                Err(_) => undelivered.push((friend, bundle)),
+            }
+        }
+
+        // Whoever answered just now is online and can carry mail
+        for (friend, bundle) in undelivered {
+            let mut stored = false;
+            for carrier in &report.delivered {
+                let request = SyncRequest::Store {
+                    bundle: bundle.clone(),
+                };
+                if self.sync_request(carrier, &request).await.is_ok() {
+                    stored = true;
+                }
+            }
+            if stored {
+                report.forwarded.push(friend);
+            } else {
+                report.failed.push(friend);
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Collect updates that friends have been holding for us
+    pub async fn fetch_forwarded_updates(&self) -> Vec<ShareData> {
+        let friends = self.friends.read().await.endpoint_ids();
+        let secret_key = self.endpoint.secret_key();
+
+        // Several carriers may hold a copy; keep the newest per author
+        let mut newest: HashMap<String, SealedBundle> = HashMap::new();
+        for friend in friends {
+            let Ok(response) = self.sync_request(&friend, &SyncRequest::Fetch).await else {
+                continue;
+            };
+            for bundle in response.bundles {
+                let is_newer = match newest.get(&bundle.from) {
+                    Some(current) => current.created_at < bundle.created_at,
+                    None => true,
+                };
+                if is_newer {
+                    newest.insert(bundle.from.clone(), bundle);
+                }
+            }
+        }
+
+        let mut received = Vec::new();
+        for (author, bundle) in newest {
+            // Skips non-friends, and replayed or stale bundles
+            if !self
+                .friends
+                .read()
+                .await
+                .is_newer_bundle(&author, bundle.created_at)
+            {
+                continue;
+            }
+            let share_data = match bundle.open(secret_key) {
+                Ok(share_data) => share_data,
+                Err(message) => {
+                    self.events.emit(FriendEvent::Error { message });
+                    continue;
+                }
+            };
+
+            let recorded = self.friends.write().await.record_bundle(
+                author,
+                bundle.created_at,
+                share_data.clone(),
+            );
+            if !recorded {
+                continue;
+            }
+            self.events.emit(FriendEvent::DataReceived {
+                share_data: share_data.clone(),
             });
+            received.push(share_data);
         }
+        received
+    }
 
-        Ok(their_share_data)
+    async fn sync_request(
+        &self,
+        peer: &str,
+        request: &SyncRequest,
+    ) -> Result<SyncResponse, String> {
+        let endpoint_id =
+            PublicKey::from_str(peer).map_err(|e| format!("Invalid endpoint ID: {}", e))?;
+
+        let data = serde_json::to_vec(request)
+            .map_err(|e| format!("Failed to serialize sync request: {}", e))?;
+
+        // A peer that accepts and then never answers must not hold us up
+        // any longer than one that is offline
+        let exchange = async {
+            let conn = self
+                .endpoint
+                .connect(EndpointAddr::from(endpoint_id), SYNC_ALPN)
+                .await
+                .map_err(|e| format!("Failed to connect: {}", e))?;
+
+            let (mut send, mut recv) = conn
+                .open_bi()
+                .await
+                .map_err(|e| format!("Failed to open stream: {}", e))?;
+            send.write_all(&data)
+                .await
+                .map_err(|e| format!("Failed to send sync request: {}", e))?;
+            send.finish()
+                .map_err(|e| format!("Failed to finish send: {}", e))?;
+
+            let bytes = recv
+                .read_to_end(MAX_SYNC_MESSAGE_LEN)
+                .await
+                .map_err(|e| format!("Failed to read sync response: {}", e))?;
+            conn.close(0u32.into(), b"bye!");
+            Ok::<_, String>(bytes)
+        };
+        let bytes = tokio::time::timeout(SYNC_TIMEOUT, exchange)
+            .await
+            .map_err(|_| "Timed out syncing with peer".to_string())??;
+
+        let response: SyncResponse = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("Failed to parse sync response: {}", e))?;
+        if !response.ok {
+            return Err(response
+                .error
+                .unwrap_or_else(|| "Sync request failed".to_string()));
+        }
+        Ok(response)
     }
 
     /// Accept a pending friend request
@@ -333,51 +849,21 @@ impl FriendExchangeService {
         let pending_req = pending.remove(idx);
         drop(pending); // Release lock
 
-        let mut send = pending_req
+        let send = pending_req
             .response_send
             .ok_or("Response stream not available")?;
 
-        let response = FriendResponseMessage { accepted: true };
-        let bytes = serde_json::to_vec(&response)
-            .map_err(|e| format!("Failed to serialize response: {}", e))?;
+        let their_share_data =
+            complete_exchange(&pending_req.connection, send, &my_share_data).await?;
 
-        send.write_all(&bytes)
-            .await
-            .map_err(|e| format!("Failed to send response: {}", e))?;
-        send.finish()
-            .map_err(|e| format!("Failed to finish response: {}", e))?;
-
-        let (mut send2, mut recv2) = pending_req
-            .connection
-            .accept_bi()
-            .await
-            .map_err(|e| format!("Failed to accept data stream: {}", e))?;
-
-        let their_data_bytes = recv2
-            .read_to_end(100000)
-            .await
-            .map_err(|e| format!("Failed to read their data: {}", e))?;
-        let their_share_data: ShareData = serde_json::from_slice(&their_data_bytes)
-            .map_err(|e| format!("Failed to parse their data: {}", e))?;
-
-        let share_data_bytes = serde_json::to_vec(&my_share_data)
-            .map_err(|e| format!("Failed to serialize share data: {}", e))?;
-
-        send2
-            .write_all(&share_data_bytes)
+        self.friends
+            .write()
             .await
-            .map_err(|e| format!("Failed to send share data: {}", e))?;
-        send2
-            .finish()
-            .map_err(|e| format!("Failed to finish data send: {}", e))?;
+            .record_exchange(remote_id, their_share_data.clone());
 
-        pending_req.connection.closed().await;
-
-        if let Some(tx) = self.event_tx.lock().await.as_ref() {
-            let _ = tx.send(FriendEvent::RequestAccepted {
-                share_data: their_share_data.clone(),
-            });
-        }
+        self.events.emit(FriendEvent::RequestAccepted {
+            share_data: their_share_data.clone(),
+        });
 
         Ok(their_share_data)
     }
@@ -411,13 +897,17 @@ impl FriendExchangeService {
         Ok(())
     }
 
-    /// Shutdown the service
-    pub async fn shutdown(self) -> Result<(), String> {
-        if let Some(handle) = self.discovery_task.lock().await.take() {
-            handle.abort();
+    /// Shutdown the service. Safe to call more than once.
+    pub async fn shutdown(&self) -> Result<(), String> {
+        self.stop_discovery().await;
+
+        // Requests still waiting for an answer can't be answered any more
+        for pending in self.pending_requests.write().await.drain(..) {
+            pending.connection.close(0u32.into(), b"shutting down");
         }
 
-        if let Some(router) = self.router {
+        let router = self.router.lock().await.take();
+        if let Some(router) = router {
             router
                 .shutdown()
                 .await
@@ -428,14 +918,129 @@ impl FriendExchangeService {
     }
 }
 
+pub(crate) fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Refresh a sighting; returns the peer only if it is new or its details changed
+async fn upsert_peer(
+    peers: &RwLock<HashMap<String, DiscoveredPeer>>,
+    endpoint_id: String,
+    advert: Advertisement,
+    is_friend: bool,
+) -> Option<DiscoveredPeer> {
+    let now = unix_timestamp();
+    let mut peers = peers.write().await;
+    let first_seen = peers.get(&endpoint_id).map_or(now, |peer| peer.first_seen);
+
+    let peer = DiscoveredPeer {
+        endpoint_id: endpoint_id.clone(),
+        compatible: advert.is_compatible(),
+        name: advert.name,
+        semester: advert.semester,
+        app_version: advert.app_version,
+        profile_hash: advert.profile_hash,
+        timestamp: now,
+        first_seen,
+        is_friend,
+    };
+
+    // Compare everything except the sighting time
+    let changed = match peers.get(&endpoint_id) {
+        Some(old) => {
+            let mut old = old.clone();
+            old.timestamp = now;
+            old != peer
+        }
+        None => true,
+    };
+    peers.insert(endpoint_id, peer.clone());
+    changed.then_some(peer)
+}
+
+/// Drop peers whose last announcement is older than `PEER_TTL`
+async fn prune_stale_peers(peers: &RwLock<HashMap<String, DiscoveredPeer>>) -> Vec<String> {
+    let cutoff = unix_timestamp().saturating_sub(PEER_TTL.as_secs());
+    let mut peers = peers.write().await;
+    let stale: Vec<String> = peers
+        .values()
+        .filter(|peer| peer.timestamp < cutoff)
+        .map(|peer| peer.endpoint_id.clone())
+        .collect();
+    for endpoint_id in &stale {
+        peers.remove(endpoint_id);
+    }
+    stale
+}
+
 // ============================================================================
 // Protocol Handler (Internal)
 // ============================================================================
 
+/// Answer an accepted request and swap share data on the same connection
+async fn complete_exchange(
+    connection: &Connection,
+    mut send: iroh::endpoint::SendStream,
+    my_share_data: &ShareData,
+) -> Result<ShareData, String> {
+    let response = FriendResponseMessage { accepted: true };
+    let bytes = serde_json::to_vec(&response)
+        .map_err(|e| format!("Failed to serialize response: {}", e))?;
+
+    send.write_all(&bytes)
+        .await
+        .map_err(|e| format!("Failed to send response: {}", e))?;
+    send.finish()
+        .map_err(|e| format!("Failed to finish response: {}", e))?;
+
+    let (mut send2, mut recv2) = connection
+        .accept_bi()
+        .await
+        .map_err(|e| format!("Failed to accept data stream: {}", e))?;
+
+    let their_data_bytes = recv2
+        .read_to_end(100000)
+        .await
+        .map_err(|e| format!("Failed to read their data: {}", e))?;
+    let their_share_data = share_data_from_slice(&their_data_bytes)
+        .map_err(|e| format!("Failed to parse their data: {}", e))?;
+
+    let share_data_bytes = serde_json::to_vec(my_share_data)
+        .map_err(|e| format!("Failed to serialize share data: {}", e))?;
+
+    send2
+        .write_all(&share_data_bytes)
+        .await
+        .map_err(|e| format!("Failed to send share data: {}", e))?;
+    send2
+        .finish()
+        .map_err(|e| format!("Failed to finish data send: {}", e))?;
+
+    connection.closed().await;
+
+    Ok(their_share_data)
+}
+
 #[derive(Clone, Debug)]
 struct FriendProtocolHandler {
-    event_tx: Arc<Mutex<Option<mpsc::UnboundedSender<FriendEvent>>>>,
+    events: EventHub,
     pending_requests: Arc<RwLock<Vec<PendingRequest>>>,
+    my_share_data: Arc<RwLock<Option<ShareData>>>,
+    invites: Arc<RwLock<HashMap<String, Instant>>>,
+    friends: Arc<RwLock<FriendRegistry>>,
+}
+
+impl FriendProtocolHandler {
+    /// Consume an invite; true only if it was issued by us and is unexpired
+    async fn redeem_invite(&self, invite: &str) -> bool {
+        match self.invites.write().await.remove(invite) {
+            Some(expires_at) => expires_at > Instant::now(),
+            None => false,
+        }
+    }
 }
 
 impl ProtocolHandler for FriendProtocolHandler {
@@ -463,6 +1068,32 @@ impl ProtocolHandler for FriendProtocolHandler {
             remote_id: remote_id.clone(),
         };
 
+        // A valid invite means the user already consented by showing their
+        // code. It is only spent once there is share data to answer with, so
+        // the request can be retried after the share data is set.
+        let my_share_data = match request.invite.as_deref() {
+            Some(invite) => match self.my_share_data.read().await.clone() {
+                Some(share_data) if self.redeem_invite(invite).await => Some(share_data),
+                _ => None,
+            },
+            None => None,
+        };
+
+        if let Some(my_share_data) = my_share_data {
+            let event = match complete_exchange(&connection, send, &my_share_data).await {
+                Ok(share_data) => {
+                    self.friends
+                        .write()
+                        .await
+                        .record_exchange(remote_id, share_data.clone());
+                    FriendEvent::RequestAccepted { share_data }
+                }
+                Err(message) => FriendEvent::Error { message },
+            };
+            self.events.emit(event);
+            return Ok(());
+        }
+
         let pending = PendingRequest {
             remote_id: remote_id.clone(),
             connection: Arc::new(connection),
@@ -471,9 +1102,8 @@ impl ProtocolHandler for FriendProtocolHandler {
         };
         self.pending_requests.write().await.push(pending);
 
-        if let Some(tx) = self.event_tx.lock().await.as_ref() {
-            let _ = tx.send(FriendEvent::IncomingRequest { request: incoming });
-        }
+        self.events
+            .emit(FriendEvent::IncomingRequest { request: incoming });
 
         Ok(())
     }
@@ -492,127 +1122,3 @@ impl ProtocolHandler for FriendProtocolHandler {
         async move {}
     }
 }
-
-// ============================================================================
-// Tauri Commands
-// ============================================================================
-
-pub type ServiceState = Arc<Mutex<Option<FriendExchangeService>>>;
-
-#[tauri::command]
-pub async fn init_friend_service(state: State<'_, ServiceState>) -> Result<String, String> {
-    let service = FriendExchangeService::new().await?;
-    let endpoint_id = service.get_endpoint_id();
-    *state.lock().await = Some(service);
-    Ok(endpoint_id)
-}
-
-#[tauri::command]
-pub async fn set_share_data(
-    state: State<'_, ServiceState>,
-    share_data: ShareData,
-) -> Result<(), String> {
-    let service = state.lock().await;
-    if let Some(service) = service.as_ref() {
-        service.set_share_data(share_data).await;
-        Ok(())
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
-
-#[tauri::command]
-pub async fn start_friend_service(
-    state: State<'_, ServiceState>,
-    app_handle: tauri::AppHandle,
-) -> Result<(), String> {
-    let (tx, mut rx) = mpsc::unbounded_channel();
-
-    let app_clone = app_handle.clone();
-    tokio::spawn(async move {
-        while let Some(event) = rx.recv().await {
-            let _ = app_clone.emit("friend-event", event);
-        }
-    });
-
-    let mut service = state.lock().await;
-    if let Some(service) = service.as_mut() {
-        service.start(tx.clone()).await?;
-        Ok(())
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
-
-#[tauri::command]
-pub async fn start_discovery(state: State<'_, ServiceState>) -> Result<(), String> {
-    let service = state.lock().await;
-    if let Some(service) = service.as_ref() {
-        service.start_discovery().await;
-        Ok(())
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
-
-#[tauri::command]
-pub async fn stop_discovery(state: State<'_, ServiceState>) -> Result<(), String> {
-    let service = state.lock().await;
-    if let Some(service) = service.as_ref() {
-        service.stop_discovery().await;
-        Ok(())
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
-
-#[tauri::command]
-pub async fn send_friend_request(
-    state: State<'_, ServiceState>,
-    peer_id: String,
-    share_data: ShareData,
-) -> Result<ShareData, String> {
-    let service = state.lock().await;
-    if let Some(service) = service.as_ref() {
-        service.send_friend_request(peer_id, share_data).await
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
-
-#[tauri::command]
-pub async fn accept_friend_request(
-    state: State<'_, ServiceState>,
-    remote_id: String,
-    share_data: ShareData,
-) -> Result<ShareData, String> {
-    let service = state.lock().await;
-    if let Some(service) = service.as_ref() {
-        service.accept_friend_request(remote_id, share_data).await
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
-
-#[tauri::command]
-pub async fn reject_friend_request(
-    state: State<'_, ServiceState>,
-    remote_id: String,
-) -> Result<(), String> {
-    let service = state.lock().await;
-    if let Some(service) = service.as_ref() {
-        service.reject_friend_request(remote_id).await
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
-
-#[tauri::command]
-pub async fn get_my_endpoint_id(state: State<'_, ServiceState>) -> Result<String, String> {
-    let service = state.lock().await;
-    if let Some(service) = service.as_ref() {
-        Ok(service.get_endpoint_id())
-    } else {
-        Err("Service not initialized".to_string())
-    }
-}
\ No newline at end of file
@@ -0,0 +1,455 @@
+use iroh::SecretKey;
+use serde_json::Value;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::sync::Mutex;
+
+use crate::model::migrate_share_data;
+use crate::p2p::{
+    DiscoveredPeer, EventSink, FriendEvent, FriendExchangeService, PublishReport, ServiceStatus,
+    ShareData,
+};
+use crate::proximity::NearbySighting;
+use crate::registry::FriendRecord;
+use crate::ticket::TicketLink;
+
+// ============================================================================
+// Tauri Commands
+// ============================================================================
+
+const NOT_INITIALIZED: &str = "Service not initialized";
+const RESTARTING: &str = "Friend service is restarting; try again shortly";
+
+/// The lock only guards swapping the service in and out; commands clone the
+/// `Arc` and release it before doing any network I/O.
+pub type ServiceState = Arc<Mutex<ServiceSlot>>;
+
+#[derive(Default)]
+pub enum ServiceSlot {
+    #[default]
+    Stopped,
+    Running(Arc<FriendExchangeService>),
+    /// An endpoint is being bound by `init_friend_service` or
+    /// `restart_friend_service`; only one may hold the identity at a time
+    Restarting,
+}
+
+impl ServiceSlot {
+    pub(crate) fn running(&self) -> Option<Arc<FriendExchangeService>> {
+        match self {
+            Self::Running(service) => Some(service.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Forwards service events to the webview as `friend-event`
+struct TauriEventSink(AppHandle);
+
+impl EventSink for TauriEventSink {
+    fn emit(&self, event: FriendEvent) {
+        let _ = self.0.emit("friend-event", event);
+    }
+}
+
+pub(crate) async fn current_service(
+    state: &State<'_, ServiceState>,
+) -> Result<Arc<FriendExchangeService>, String> {
+    match &*state.lock().await {
+        ServiceSlot::Running(service) => Ok(service.clone()),
+        ServiceSlot::Restarting => Err(RESTARTING.to_string()),
+        ServiceSlot::Stopped => Err(NOT_INITIALIZED.to_string()),
+    }
+}
+
+/// Where the endpoint identity is kept, so the endpoint ID friends know
+/// survives restarts
+pub(crate) fn identity_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to resolve app data dir: {}", e))?;
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create app data dir: {}", e))?;
+    Ok(dir.join("identity.key"))
+}
+
+/// Load the endpoint identity, generating and saving one on first launch
+pub(crate) fn load_or_create_identity(app: &AppHandle) -> Result<SecretKey, String> {
+    let path = identity_path(app)?;
+    if path.exists() {
+        let bytes = std::fs::read(&path).map_err(|e| format!("Failed to read identity: {}", e))?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| "Stored identity is not a 32 byte key".to_string())?;
+        return Ok(SecretKey::from_bytes(&bytes));
+    }
+
+    let bytes = rand::random::<[u8; 32]>();
+    write_identity(&path, &bytes)?;
+    Ok(SecretKey::from_bytes(&bytes))
+}
+
+/// Save an identity readable only by the owner. It goes to a fresh temp file
+/// first and is renamed over `path`, so a failed write never leaves a
+/// truncated key behind.
+pub(crate) fn write_identity(path: &Path, bytes: &[u8; 32]) -> Result<(), String> {
+    let tmp = path.with_extension("key.tmp");
+    // Left over from a write that died halfway
+    match std::fs::remove_file(&tmp) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(format!("Failed to remove stale identity: {}", e));
+        }
+        _ => {}
+    }
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&tmp, path))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&tmp);
+            format!("Failed to save identity: {}", e)
+        })
+}
+
+/// Shut down whatever service is in `state`, leaving it stopped. A restart
+/// in progress sees this and closes its new endpoint when it is done.
+async fn take_and_shutdown(state: &ServiceState) -> Result<(), String> {
+    let slot = std::mem::take(&mut *state.lock().await);
+    match slot {
+        ServiceSlot::Running(service) => service.shutdown().await,
+        _ => Ok(()),
+    }
+}
+
+/// Install the endpoint bound by an init or restart, unless the service was
+/// shut down meanwhile. Returns the endpoint ID.
+async fn finish_restart(
+    state: &ServiceState,
+    bound: Result<FriendExchangeService, String>,
+) -> Result<String, String> {
+    let mut slot = state.lock().await;
+    let wanted = matches!(*slot, ServiceSlot::Restarting);
+    match bound {
+        Ok(service) if wanted => {
+            let endpoint_id = service.get_endpoint_id();
+            *slot = ServiceSlot::Running(Arc::new(service));
+            Ok(endpoint_id)
+        }
+        Ok(service) => {
+            drop(slot);
+            service.shutdown().await?;
+            Err("Friend service was shut down while restarting".to_string())
+        }
+        Err(e) => {
+            if wanted {
+                *slot = ServiceSlot::Stopped;
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Called on app exit so the endpoint closes its connections cleanly
+pub(crate) fn shutdown_on_exit(app: &AppHandle) {
+    let state = app.state::<ServiceState>().inner().clone();
+    tauri::async_runtime::block_on(async move {
+        if let Err(e) = take_and_shutdown(&state).await {
+            eprintln!("Friend service shutdown failed: {}", e);
+        }
+    });
+}
+
+/// Pause discovery while the app is backgrounded and bring it back after.
+/// mDNS keeps waking the radio otherwise, and the OS may kill the socket.
+#[cfg(mobile)]
+pub(crate) fn set_backgrounded(app: &AppHandle, backgrounded: bool) {
+    let state = app.state::<ServiceState>().inner().clone();
+    tauri::async_runtime::spawn(async move {
+        let Some(service) = state.lock().await.running() else {
+            return;
+        };
+        if backgrounded {
+            service.pause_discovery().await;
+        } else {
+            service.resume_discovery().await;
+        }
+    });
+}
+
+#[tauri::command]
+pub async fn init_friend_service(
+    state: State<'_, ServiceState>,
+    app_handle: AppHandle,
+) -> Result<String, String> {
+    let secret_key = load_or_create_identity(&app_handle)?;
+    let previous = std::mem::replace(&mut *state.lock().await, ServiceSlot::Restarting);
+    let old = match previous {
+        ServiceSlot::Restarting => return Err(RESTARTING.to_string()),
+        ServiceSlot::Running(old) => Some(old),
+        ServiceSlot::Stopped => None,
+    };
+
+    let bound = async {
+        // Close the old endpoint before binding the same identity again.
+        // Friends' endpoint IDs do not depend on ours, e.g. after a
+        // restored backup brings back an old identity, so they carry over.
+        let known_friends = match old {
+            Some(old) => {
+                let known_friends = old.known_friends().await;
+                old.shutdown().await?;
+                known_friends
+            }
+            None => Vec::new(),
+        };
+        let service = FriendExchangeService::with_secret_key(secret_key).await?;
+        service.set_known_friends(known_friends).await;
+        Ok::<_, String>(service)
+    }
+    .await;
+    finish_restart(&state, bound).await
+}
+
+#[tauri::command]
+pub async fn shutdown_friend_service(state: State<'_, ServiceState>) -> Result<(), String> {
+    take_and_shutdown(&state).await
+}
+
+/// Rebind the endpoint, keeping the identity and everything the app set.
+/// Other commands get a "restarting" error until it is done. If it fails
+/// the service is left stopped; call `init_friend_service`.
+#[tauri::command]
+pub async fn restart_friend_service(state: State<'_, ServiceState>) -> Result<String, String> {
+    // Mark it under the lock; the restart itself is network I/O
+    let old = {
+        let mut slot = state.lock().await;
+        let old = match &*slot {
+            ServiceSlot::Running(service) => service.clone(),
+            ServiceSlot::Restarting => return Err(RESTARTING.to_string()),
+            ServiceSlot::Stopped => return Err(NOT_INITIALIZED.to_string()),
+        };
+        *slot = ServiceSlot::Restarting;
+        old
+    };
+
+    let bound = match old.restart().await {
+        Ok(service) => Ok(service),
+        Err(e) => {
+            // Make sure nothing of the old endpoint is left half open
+            let _ = old.shutdown().await;
+            Err(format!("Restart failed, friend service stopped: {}", e))
+        }
+    };
+    finish_restart(&state, bound).await
+}
+
+#[tauri::command]
+pub async fn friend_service_status(
+    state: State<'_, ServiceState>,
+) -> Result<ServiceStatus, String> {
+    let service = state.lock().await.running();
+    match service {
+        Some(service) => Ok(service.status().await),
+        None => Ok(ServiceStatus::default()),
+    }
+}
+
+#[tauri::command]
+pub async fn set_share_data(
+    state: State<'_, ServiceState>,
+    share_data: Value,
+) -> Result<(), String> {
+    current_service(&state)
+        .await?
+        .set_share_data(migrate_share_data(share_data)?)
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn start_friend_service(
+    state: State<'_, ServiceState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    current_service(&state)
+        .await?
+        .start(TauriEventSink(app_handle))
+        .await
+}
+
+#[tauri::command]
+pub async fn start_discovery(state: State<'_, ServiceState>) -> Result<(), String> {
+    current_service(&state).await?.start_discovery().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_discovery(state: State<'_, ServiceState>) -> Result<(), String> {
+    current_service(&state).await?.stop_discovery().await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn list_nearby_peers(
+    state: State<'_, ServiceState>,
+) -> Result<Vec<DiscoveredPeer>, String> {
+    Ok(current_service(&state).await?.list_nearby_peers().await)
+}
+
+#[tauri::command]
+pub async fn set_proximity_enabled(
+    state: State<'_, ServiceState>,
+    enabled: bool,
+) -> Result<(), String> {
+    current_service(&state)
+        .await?
+        .set_proximity_enabled(enabled)
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn set_nearby_watch(
+    state: State<'_, ServiceState>,
+    endpoint_id: String,
+    watched: bool,
+) -> Result<(), String> {
+    current_service(&state)
+        .await?
+        .set_nearby_watch(endpoint_id, watched)
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_nearby_history(
+    state: State<'_, ServiceState>,
+) -> Result<Vec<NearbySighting>, String> {
+    Ok(current_service(&state).await?.nearby_history().await)
+}
+
+#[tauri::command]
+pub async fn set_known_friends(
+    state: State<'_, ServiceState>,
+    friends: Vec<FriendRecord>,
+) -> Result<(), String> {
+    current_service(&state)
+        .await?
+        .set_known_friends(friends)
+        .await;
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn send_friend_request(
+    state: State<'_, ServiceState>,
+    peer_id: String,
+    share_data: Value,
+) -> Result<ShareData, String> {
+    current_service(&state)
+        .await?
+        .send_friend_request(peer_id, migrate_share_data(share_data)?)
+        .await
+}
+
+#[tauri::command]
+pub async fn create_friend_ticket(state: State<'_, ServiceState>) -> Result<TicketLink, String> {
+    current_service(&state)
+        .await?
+        .create_ticket()
+        .await
+        .to_link()
+}
+
+#[tauri::command]
+pub async fn send_friend_request_with_ticket(
+    state: State<'_, ServiceState>,
+    ticket: String,
+    share_data: Value,
+) -> Result<ShareData, String> {
+    current_service(&state)
+        .await?
+        .send_friend_request_with_ticket(&ticket, migrate_share_data(share_data)?)
+        .await
+}
+
+#[tauri::command]
+pub async fn publish_share_update(state: State<'_, ServiceState>) -> Result<PublishReport, String> {
+    current_service(&state).await?.publish_share_update().await
+}
+
+#[tauri::command]
+pub async fn fetch_forwarded_updates(
+    state: State<'_, ServiceState>,
+) -> Result<Vec<ShareData>, String> {
+    Ok(current_service(&state)
+        .await?
+        .fetch_forwarded_updates()
+        .await)
+}
+
+#[tauri::command]
+pub async fn accept_friend_request(
+    state: State<'_, ServiceState>,
+    remote_id: String,
+    share_data: Value,
+) -> Result<ShareData, String> {
+    current_service(&state)
+        .await?
+        .accept_friend_request(remote_id, migrate_share_data(share_data)?)
+        .await
+}
+
+#[tauri::command]
+pub async fn reject_friend_request(
+    state: State<'_, ServiceState>,
+    remote_id: String,
+) -> Result<(), String> {
+    current_service(&state)
+        .await?
+        .reject_friend_request(remote_id)
+        .await
+}
+
+#[tauri::command]
+pub async fn get_my_endpoint_id(state: State<'_, ServiceState>) -> Result<String, String> {
+    Ok(current_service(&state).await?.get_endpoint_id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_replaces_the_old_one_owner_only() {
+        let dir = std::env::temp_dir().join(format!("vfriend-identity-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("identity.key");
+        // A stale temp file from an interrupted write does not block saving
+        std::fs::write(path.with_extension("key.tmp"), b"partial").unwrap();
+
+        write_identity(&path, &[1; 32]).unwrap();
+        write_identity(&path, &[2; 32]).unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), [2; 32]);
+        assert!(!path.with_extension("key.tmp").exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
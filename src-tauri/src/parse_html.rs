@@ -1,24 +1,6 @@
-use core::time;
-use serde::{Deserialize, Serialize};
 use soup::prelude::*;
-use std::fs;
-
-#[derive(Debug, Serialize, Deserialize)]
-struct CompactTimetable {
-    u: String,           // username
-    r: String,           // registration number
-    s: i32,              // semester
-    t: String,           // timestamp
-    o: Vec<CompactSlot>, // occupied slots
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct CompactSlot {
-    d: u8,     // day (1-7)
-    s: String, // slot_type (t/l)
-    p: u8,     // period
-    f: String, // full_text (original cell text)
-}
+use crate::model::{CompactSlot, ShareData, SCHEMA_VERSION};
 
 // Helper function to extract course code and room information
 fn extract_course_info(cell_text: &str) -> String {
@@ -102,7 +84,7 @@ pub fn parse_html(html_content: String) -> Result<String, String> {
                             occupied_slots.push(CompactSlot {
                                 d: day_num,
                                 s: "t".to_string(), // theory
-                                p: period as u8,
+                                p: period,
                                 f: extract_course_info(&cell_text),
                             });
                         }
@@ -121,7 +103,7 @@ pub fn parse_html(html_content: String) -> Result<String, String> {
                     }
 
                     // Check for green background
-                    let has_green_bg = cell.get("bgcolor").map_or(false, |bg| bg == "#CCFF33");
+                    let has_green_bg = cell.get("bgcolor").is_some_and(|bg| bg == "#CCFF33");
 
                     if has_green_bg {
                         let cell_text = cell.text().trim().to_string();
@@ -147,10 +129,13 @@ pub fn parse_html(html_content: String) -> Result<String, String> {
     }
 
     // Step 5: Create compact timetable
-    let timetable = CompactTimetable {
+    let timetable = ShareData {
+        v: SCHEMA_VERSION,
         u: "ppmpreetham".to_string(),
         r: registration_number,
         s: 0,
+        h: Vec::new(),
+        q: Vec::new(),
         t: "2025-06-15T11:34:53+00:00".to_string(),
         o: occupied_slots,
     };
@@ -161,21 +146,3 @@ pub fn parse_html(html_content: String) -> Result<String, String> {
 
     Ok(json)
 }
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Step 1: Load HTML file
-    let html = fs::read_to_string("VIT Chennai - VTOP.html")?;
-
-    // Step 2: Parse HTML and get JSON
-    let json_result = parse_html(html)?;
-
-    // Step 3: Print the result
-    println!("Generated compact JSON:");
-    println!("{}", json_result);
-
-    // Step 4: Save JSON to file
-    std::fs::write("timetable_minimal.json", &json_result)?;
-    println!("Minimal timetable JSON saved to timetable_minimal.json");
-
-    Ok(())
-}
@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::p2p::unix_timestamp;
+
+const MAX_HISTORY: usize = 200;
+
+// ============================================================================
+// Nearby Friends
+// ============================================================================
+
+/// A friend's endpoint seen over mDNS, i.e. on the same local network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NearbySighting {
+    pub endpoint_id: String,
+    pub name: String,
+    pub network: Option<String>, // e.g. "192.168.1.0/24"
+    pub seen_at: u64,
+}
+
+/// Opt-in proximity detection. Nothing is recorded until the mode is
+/// enabled, and then only for friends that were explicitly watched.
+#[derive(Debug, Default)]
+pub struct ProximityTracker {
+    enabled: bool,
+    watched: HashSet<String>,
+    history: VecDeque<NearbySighting>,
+}
+
+impl ProximityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn set_watched(&mut self, endpoint_id: String, watched: bool) {
+        if watched {
+            self.watched.insert(endpoint_id);
+        } else {
+            self.watched.remove(&endpoint_id);
+        }
+    }
+
+    /// Record a sighting if proximity mode is on and the friend opted in
+    pub fn observe<'a>(
+        &mut self,
+        endpoint_id: &str,
+        name: &str,
+        addrs: impl IntoIterator<Item = &'a SocketAddr>,
+    ) -> Option<NearbySighting> {
+        if !self.enabled || !self.watched.contains(endpoint_id) {
+            return None;
+        }
+
+        let sighting = NearbySighting {
+            endpoint_id: endpoint_id.to_string(),
+            name: name.to_string(),
+            network: network_name(addrs),
+            seen_at: unix_timestamp(),
+        };
+
+        if self.history.len() == MAX_HISTORY {
+            self.history.pop_front();
+        }
+        self.history.push_back(sighting.clone());
+        Some(sighting)
+    }
+
+    /// Sightings, newest first
+    pub fn history(&self) -> Vec<NearbySighting> {
+        self.history.iter().rev().cloned().collect()
+    }
+}
+
+/// Describe the LAN a peer was seen on by the /24 of its first private IPv4
+fn network_name<'a>(addrs: impl IntoIterator<Item = &'a SocketAddr>) -> Option<String> {
+    addrs.into_iter().find_map(|addr| match addr.ip() {
+        IpAddr::V4(ip) if ip.is_private() => {
+            let [a, b, c, _] = ip.octets();
+            Some(format!("{}.{}.{}.0/24", a, b, c))
+        }
+        _ => None,
+    })
+}
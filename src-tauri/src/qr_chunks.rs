@@ -0,0 +1,305 @@
+//! Splits a share code across an animated QR sequence.
+//!
+//! Every frame is `vfq1/<set>/<index>/<total>/<data>`: `set` is eight hex
+//! characters of the SHA-256 of the whole code, so frames from a different
+//! code are rejected and the joined result is checked before decoding.
+//! Frames can be scanned in any order and repeats are ignored.
+
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tauri::State;
+
+use crate::model::migrate_share_data;
+use crate::p2p::ShareData;
+use crate::share_codec::{decode_share_data, encode_share_data};
+
+const CHUNK_PREFIX: &str = "vfq1/";
+
+/// Keeps each frame well inside what a phone camera reads reliably
+const DEFAULT_CHUNK_LEN: usize = 300;
+const MIN_CHUNK_LEN: usize = 32;
+const MAX_CHUNKS: usize = 999;
+
+fn set_id(payload: &str) -> String {
+    Sha256::digest(payload.as_bytes())[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Split `payload` into frames of at most `max_len` data characters
+pub fn split_payload(payload: &str, max_len: usize) -> Result<Vec<String>, String> {
+    let max_len = max_len.max(MIN_CHUNK_LEN);
+    // Share codes are base64url, so byte offsets are char boundaries
+    if !payload.is_ascii() {
+        return Err("QR payload must be ASCII".to_string());
+    }
+    let total = payload.len().div_ceil(max_len).max(1);
+    if total > MAX_CHUNKS {
+        return Err(format!(
+            "Payload needs {} QR frames, more than the {} allowed",
+            total, MAX_CHUNKS
+        ));
+    }
+
+    let set = set_id(payload);
+    Ok((0..total)
+        .map(|index| {
+            let start = index * max_len;
+            let end = (start + max_len).min(payload.len());
+            format!(
+                "{}{}/{}/{}/{}",
+                CHUNK_PREFIX,
+                set,
+                index + 1,
+                total,
+                &payload[start..end]
+            )
+        })
+        .collect())
+}
+
+struct Chunk<'a> {
+    set: &'a str,
+    index: usize,
+    total: usize,
+    data: &'a str,
+}
+
+fn parse_chunk(frame: &str) -> Result<Chunk<'_>, String> {
+    let body = frame
+        .trim()
+        .strip_prefix(CHUNK_PREFIX)
+        .ok_or("Not a VFriend QR frame")?;
+    let mut parts = body.splitn(4, '/');
+    let (Some(set), Some(index), Some(total), Some(data)) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err("QR frame is missing fields".to_string());
+    };
+
+    let index: usize = index
+        .parse()
+        .map_err(|e| format!("Invalid frame index: {}", e))?;
+    let total: usize = total
+        .parse()
+        .map_err(|e| format!("Invalid frame count: {}", e))?;
+    if set.len() != 8 || !(1..=MAX_CHUNKS).contains(&total) || !(1..=total).contains(&index) {
+        return Err(format!("QR frame {}/{} is out of range", index, total));
+    }
+    Ok(Chunk {
+        set,
+        index,
+        total,
+        data,
+    })
+}
+
+/// Progress of an in-progress scan
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanProgress {
+    pub received: usize,
+    pub total: usize,
+    pub missing: Vec<usize>,           // 1-based frame numbers still needed
+    pub share_data: Option<ShareData>, // set once every frame is in
+}
+
+/// Collects frames of one sequence in any order
+#[derive(Debug, Default)]
+pub struct QrAssembler {
+    set: String,
+    total: usize,
+    parts: BTreeMap<usize, String>,
+}
+
+impl QrAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Add a frame. A frame from a different sequence restarts the scan,
+    /// since the camera has moved on to another code.
+    pub fn add(&mut self, frame: &str) -> Result<ScanProgress, String> {
+        // A short code fits one plain QR with no framing
+        if !frame.trim().starts_with(CHUNK_PREFIX) {
+            let share_data = decode_share_data(frame)?;
+            self.reset();
+            return Ok(ScanProgress {
+                received: 1,
+                total: 1,
+                missing: Vec::new(),
+                share_data: Some(share_data),
+            });
+        }
+
+        let chunk = parse_chunk(frame)?;
+        if chunk.set != self.set || chunk.total != self.total {
+            self.set = chunk.set.to_string();
+            self.total = chunk.total;
+            self.parts.clear();
+        }
+        self.parts
+            .entry(chunk.index)
+            .or_insert_with(|| chunk.data.to_string());
+
+        let missing: Vec<usize> = (1..=self.total)
+            .filter(|i| !self.parts.contains_key(i))
+            .collect();
+        let (received, total) = (self.parts.len(), self.total);
+        let share_data = if missing.is_empty() {
+            Some(self.finish()?)
+        } else {
+            None
+        };
+
+        Ok(ScanProgress {
+            received,
+            total,
+            missing,
+            share_data,
+        })
+    }
+
+    fn finish(&mut self) -> Result<ShareData, String> {
+        let payload: String = self.parts.values().map(String::as_str).collect();
+        let expected = std::mem::take(&mut self.set);
+        self.reset();
+        if set_id(&payload) != expected {
+            return Err("QR frames don't add up to the original code; rescan".to_string());
+        }
+        Ok(decode_share_data(&payload)?)
+    }
+}
+
+pub type QrScanState = Mutex<QrAssembler>;
+
+#[tauri::command]
+pub fn split_share_qr(
+    share_data: Value,
+    max_chunk_len: Option<usize>,
+) -> Result<Vec<String>, String> {
+    let code = encode_share_data(&migrate_share_data(share_data)?)?;
+    split_payload(&code, max_chunk_len.unwrap_or(DEFAULT_CHUNK_LEN))
+}
+
+/// Feed one scanned frame; returns the share data once all frames are in
+#[tauri::command]
+pub fn scan_qr_frame(state: State<'_, QrScanState>, frame: String) -> Result<ScanProgress, String> {
+    state.lock().unwrap().add(&frame)
+}
+
+#[tauri::command]
+pub fn reset_qr_scan(state: State<'_, QrScanState>) {
+    state.lock().unwrap().reset();
+}
+
+/// Decode a complete set of frames in one go, in any order
+#[tauri::command]
+pub fn join_qr_frames(frames: Vec<String>) -> Result<ShareData, String> {
+    let mut assembler = QrAssembler::new();
+    for frame in &frames {
+        if let Some(share_data) = assembler.add(frame)?.share_data {
+            return Ok(share_data);
+        }
+    }
+    Err("Some QR frames are missing".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{share_data, slot};
+
+    fn code(name: &str) -> String {
+        let o = (1..=12)
+            .map(|p| {
+                let f = format!("A{}-BCSE1{:02}L-TH-SJT-{}01-ALL", p, p, p % 8);
+                slot(1 + p % 5, "t", p, &f)
+            })
+            .collect();
+        encode_share_data(&ShareData {
+            r: "23BCE0001".to_string(),
+            ..share_data(name, o)
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn frames_join_in_any_order_with_repeats() {
+        let code = code("Sana");
+        let mut frames = split_payload(&code, MIN_CHUNK_LEN).unwrap();
+        assert!(frames.len() > 3);
+        assert!(frames.iter().all(|f| f.starts_with(CHUNK_PREFIX)));
+
+        frames.reverse();
+        frames.insert(1, frames[0].clone());
+        let mut assembler = QrAssembler::new();
+        let mut last = None;
+        for frame in &frames {
+            last = Some(assembler.add(frame).unwrap());
+        }
+        let last = last.unwrap();
+        assert!(last.missing.is_empty());
+        assert_eq!(last.share_data.unwrap().u, "Sana");
+        assert_eq!(join_qr_frames(frames).unwrap().u, "Sana");
+    }
+
+    #[test]
+    fn progress_lists_missing_frames() {
+        let frames = split_payload(&code("Sana"), MIN_CHUNK_LEN).unwrap();
+        let mut assembler = QrAssembler::new();
+        let progress = assembler.add(&frames[1]).unwrap();
+        assert_eq!(progress.received, 1);
+        assert_eq!(progress.total, frames.len());
+        assert_eq!(progress.missing[0], 1);
+        assert!(!progress.missing.contains(&2));
+        assert!(progress.share_data.is_none());
+        assert!(join_qr_frames(frames[1..].to_vec()).is_err());
+    }
+
+    #[test]
+    fn a_frame_from_another_code_restarts_the_scan() {
+        let first = split_payload(&code("Sana"), MIN_CHUNK_LEN).unwrap();
+        let second = split_payload(&code("Rahul"), MIN_CHUNK_LEN).unwrap();
+        let mut assembler = QrAssembler::new();
+        assembler.add(&first[0]).unwrap();
+        let progress = assembler.add(&second[0]).unwrap();
+        assert_eq!(progress.received, 1);
+
+        let mut result = None;
+        for frame in &second[1..] {
+            result = assembler.add(frame).unwrap().share_data;
+        }
+        assert_eq!(result.unwrap().u, "Rahul");
+    }
+
+    #[test]
+    fn tampered_frames_are_refused() {
+        let mut frames = split_payload(&code("Sana"), MIN_CHUNK_LEN).unwrap();
+        let last = frames.pop().unwrap();
+        let swapped = if last.ends_with('A') { 'B' } else { 'A' };
+        frames.push(format!("{}{}", &last[..last.len() - 1], swapped));
+        let error = join_qr_frames(frames).unwrap_err();
+        assert!(error.contains("rescan"), "{}", error);
+
+        assert!(parse_chunk("vfq1/abcd/1/2/xyz").is_err());
+        assert!(parse_chunk("vfq1/abcdef12/3/2/xyz").is_err());
+        assert!(parse_chunk("vfq1/abcdef12/1").is_err());
+    }
+
+    #[test]
+    fn short_codes_pass_through_unframed() {
+        let code = code("Sana");
+        assert_eq!(split_payload(&code, 10_000).unwrap().len(), 1);
+        let progress = QrAssembler::new().add(&code).unwrap();
+        assert_eq!(progress.share_data.unwrap().u, "Sana");
+        assert!(split_payload("ü", 100).is_err());
+    }
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::model::deserialize_migrated;
+use crate::p2p::{unix_timestamp, ShareData};
+
+// ============================================================================
+// Friend Registry
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FriendRecord {
+    pub endpoint_id: String,
+    pub name: String,
+    #[serde(default, deserialize_with = "deserialize_migrated")]
+    pub share_data: Option<ShareData>,
+    #[serde(default)]
+    pub added_at: u64,
+    /// `created_at` of the newest forwarded bundle applied from them; older
+    /// or repeated bundles are replays and are refused
+    #[serde(default)]
+    pub bundle_at: u64,
+}
+
+/// Friends we have exchanged data with, keyed by endpoint ID
+#[derive(Debug, Default)]
+pub struct FriendRegistry {
+    friends: HashMap<String, FriendRecord>,
+}
+
+impl FriendRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_friend(&self, endpoint_id: &str) -> bool {
+        self.friends.contains_key(endpoint_id)
+    }
+
+    pub fn endpoint_ids(&self) -> Vec<String> {
+        self.friends.keys().cloned().collect()
+    }
+
+    pub fn records(&self) -> Vec<FriendRecord> {
+        self.friends.values().cloned().collect()
+    }
+
+    /// Store the latest share data received from a friend
+    pub fn record_exchange(&mut self, endpoint_id: String, share_data: ShareData) {
+        let (added_at, bundle_at) = match self.friends.get(&endpoint_id) {
+            Some(f) => (f.added_at, f.bundle_at),
+            None => (unix_timestamp(), 0),
+        };
+
+        let record = FriendRecord {
+            endpoint_id: endpoint_id.clone(),
+            name: share_data.u.clone(),
+            share_data: Some(share_data),
+            added_at,
+            bundle_at,
+        };
+        self.friends.insert(endpoint_id, record);
+    }
+
+    /// Whether a bundle from `author` created at `created_at` is newer than
+    /// every bundle already applied from them
+    pub fn is_newer_bundle(&self, author: &str, created_at: u64) -> bool {
+        self.friends
+            .get(author)
+            .is_some_and(|f| created_at > f.bundle_at)
+    }
+
+    /// Store share data from a forwarded bundle, unless an equal or newer
+    /// bundle from the same author was already applied. Returns whether it
+    /// was stored.
+    pub fn record_bundle(
+        &mut self,
+        author: String,
+        created_at: u64,
+        share_data: ShareData,
+    ) -> bool {
+        if !self.is_newer_bundle(&author, created_at) {
+            return false;
+        }
+        self.record_exchange(author.clone(), share_data);
+        if let Some(record) = self.friends.get_mut(&author) {
+            record.bundle_at = created_at;
+        }
+        true
+    }
+
+    /// Replace the registry with the friend list kept by the frontend. A
+    /// stale list cannot move a friend's `bundle_at` backwards.
+    pub fn replace_all(&mut self, records: Vec<FriendRecord>) {
+        let previous = std::mem::take(&mut self.friends);
+        self.friends = records
+            .into_iter()
+            .map(|mut record| {
+                if let Some(old) = previous.get(&record.endpoint_id) {
+                    record.bundle_at = record.bundle_at.max(old.bundle_at);
+                }
+                (record.endpoint_id.clone(), record)
+            })
+            .collect();
+    }
+}
@@ -0,0 +1,467 @@
+//! Compact binary encoding of `ShareData` for QR codes and share links.
+//!
+//! Layout (version 1), then URL-safe base64 without padding:
+//!
+//! ```text
+//! version:u8 | u r | s:varint | h[] | q[] | t | courses[] | slots[] | checksum:[u8; 4]
+//! ```
+//!
+//! Strings are a varint length plus UTF-8, lists a varint count plus items.
+//! Each slot is one byte `day:3 | lab:1 | period:4` plus a varint index into
+//! `courses`, so a course repeated across the week is stored once. The
+//! checksum is the first four bytes of the SHA-256 of everything before it.
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+use crate::model::{migrate_share_data, CompactSlot, ShareData, SCHEMA_VERSION};
+
+pub const SHARE_CODEC_VERSION: u8 = 1;
+pub const SHARE_LINK_PREFIX: &str = "vfriend://share/";
+
+const CHECKSUM_LEN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShareCodecError {
+    Base64 { reason: String },
+    Empty,
+    UnsupportedVersion { version: u8 },
+    ChecksumMismatch,
+    Truncated { field: &'static str },
+    OutOfRange { field: &'static str, value: u64 },
+    InvalidUtf8 { field: &'static str },
+    InvalidSlot { index: usize, reason: String },
+    TrailingBytes { count: usize },
+}
+
+impl fmt::Display for ShareCodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Base64 { reason } => write!(f, "Share code is not valid base64: {}", reason),
+            Self::Empty => write!(f, "Share code is empty"),
+            Self::UnsupportedVersion { version } => write!(
+                f,
+                "Share code version {} is not supported (expected {}); update the app",
+                version, SHARE_CODEC_VERSION
+            ),
+            Self::ChecksumMismatch => {
+                write!(f, "Share code is damaged or incomplete (checksum mismatch)")
+            }
+            Self::Truncated { field } => write!(f, "Share code ends early while reading {}", field),
+            Self::OutOfRange { field, value } => {
+                write!(f, "Share code {} {} is out of range", field, value)
+            }
+            Self::InvalidUtf8 { field } => write!(f, "Share code has invalid text in {}", field),
+            Self::InvalidSlot { index, reason } => {
+                write!(f, "Slot {} is invalid: {}", index, reason)
+            }
+            Self::TrailingBytes { count } => {
+                write!(f, "Share code has {} unexpected bytes at the end", count)
+            }
+        }
+    }
+}
+
+impl From<ShareCodecError> for String {
+    fn from(error: ShareCodecError) -> Self {
+        error.to_string()
+    }
+}
+
+// ============================================================================
+// Encoding
+// ============================================================================
+
+fn put_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn put_str(out: &mut Vec<u8>, value: &str) {
+    put_varint(out, value.len() as u64);
+    out.extend_from_slice(value.as_bytes());
+}
+
+fn put_list(out: &mut Vec<u8>, values: &[String]) {
+    put_varint(out, values.len() as u64);
+    for value in values {
+        put_str(out, value);
+    }
+}
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let digest = Sha256::digest(bytes);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+/// Pack the slot header byte, rejecting values that don't fit
+fn slot_header(index: usize, slot: &CompactSlot) -> Result<u8, ShareCodecError> {
+    let invalid = |reason: String| ShareCodecError::InvalidSlot { index, reason };
+    if !(1..=7).contains(&slot.d) {
+        return Err(invalid(format!("day {} is not 1-7", slot.d)));
+    }
+    if !(1..=12).contains(&slot.p) {
+        return Err(invalid(format!("period {} is not 1-12", slot.p)));
+    }
+    let lab = match slot.s.as_str() {
+        "t" => 0,
+        "l" => 1,
+        other => return Err(invalid(format!("kind '{}' is not \"t\" or \"l\"", other))),
+    };
+    Ok(((slot.d - 1) << 5) | (lab << 4) | slot.p)
+}
+
+/// Encode share data as a URL-safe share code
+pub fn encode_share_data(data: &ShareData) -> Result<String, ShareCodecError> {
+    let mut out = vec![SHARE_CODEC_VERSION];
+    put_str(&mut out, &data.u);
+    put_str(&mut out, &data.r);
+    put_varint(&mut out, data.s as u64);
+    put_list(&mut out, &data.h);
+    put_list(&mut out, &data.q);
+    put_str(&mut out, &data.t);
+
+    let mut courses: Vec<String> = Vec::new();
+    let mut slots = Vec::with_capacity(data.o.len());
+    for (index, slot) in data.o.iter().enumerate() {
+        let header = slot_header(index, slot)?;
+        let course = match courses.iter().position(|c| *c == slot.f) {
+            Some(course) => course,
+            None => {
+                courses.push(slot.f.clone());
+                courses.len() - 1
+            }
+        };
+        slots.push((header, course));
+    }
+
+    put_list(&mut out, &courses);
+    put_varint(&mut out, slots.len() as u64);
+    for (header, course) in slots {
+        out.push(header);
+        put_varint(&mut out, course as u64);
+    }
+
+    let sum = checksum(&out);
+    out.extend_from_slice(&sum);
+    Ok(URL_SAFE_NO_PAD.encode(out))
+}
+
+// ============================================================================
+// Decoding
+// ============================================================================
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self, field: &'static str) -> Result<u8, ShareCodecError> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or(ShareCodecError::Truncated { field })?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn varint(&mut self, field: &'static str) -> Result<u64, ShareCodecError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte(field)?;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(ShareCodecError::Truncated { field })
+    }
+
+    /// A count that can't be larger than the bytes left, so a corrupt
+    /// length can't trigger a huge allocation
+    fn count(&mut self, field: &'static str) -> Result<usize, ShareCodecError> {
+        let count = self.varint(field)? as usize;
+        if count > self.bytes.len() - self.pos {
+            return Err(ShareCodecError::Truncated { field });
+        }
+        Ok(count)
+    }
+
+    fn string(&mut self, field: &'static str) -> Result<String, ShareCodecError> {
+        let len = self.count(field)?;
+        let raw = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        String::from_utf8(raw.to_vec()).map_err(|_| ShareCodecError::InvalidUtf8 { field })
+    }
+
+    fn list(&mut self, field: &'static str) -> Result<Vec<String>, ShareCodecError> {
+        let count = self.count(field)?;
+        (0..count).map(|_| self.string(field)).collect()
+    }
+}
+
+/// Decode a share code, also accepting a full `vfriend://share/` link
+pub fn decode_share_data(code: &str) -> Result<ShareData, ShareCodecError> {
+    let code = code.trim();
+    let code = code.strip_prefix(SHARE_LINK_PREFIX).unwrap_or(code);
+    if code.is_empty() {
+        return Err(ShareCodecError::Empty);
+    }
+
+    let bytes = URL_SAFE_NO_PAD
+        .decode(code)
+        .map_err(|e| ShareCodecError::Base64 {
+            reason: e.to_string(),
+        })?;
+    if bytes.is_empty() {
+        return Err(ShareCodecError::Empty);
+    }
+    if bytes.len() < 1 + CHECKSUM_LEN {
+        return Err(ShareCodecError::Truncated { field: "checksum" });
+    }
+    // Checksum first, so a damaged code is not reported as a newer version
+    let (body, sum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    if checksum(body) != sum {
+        return Err(ShareCodecError::ChecksumMismatch);
+    }
+    let version = body[0];
+    if version != SHARE_CODEC_VERSION {
+        return Err(ShareCodecError::UnsupportedVersion { version });
+    }
+
+    let mut reader = Reader {
+        bytes: body,
+        pos: 1,
+    };
+    let u = reader.string("username")?;
+    let r = reader.string("registration number")?;
+    let semester = reader.varint("semester")?;
+    let s = u32::try_from(semester).map_err(|_| ShareCodecError::OutOfRange {
+        field: "semester",
+        value: semester,
+    })?;
+    let h = reader.list("hobbies")?;
+    let q = reader.list("quote")?;
+    let t = reader.string("timestamp")?;
+    let courses = reader.list("courses")?;
+
+    let slot_count = reader.count("slots")?;
+    let mut o = Vec::with_capacity(slot_count);
+    for index in 0..slot_count {
+        let header = reader.byte("slots")?;
+        let course = reader.varint("slots")? as usize;
+        let invalid = |reason: String| ShareCodecError::InvalidSlot { index, reason };
+
+        let day = (header >> 5) + 1;
+        let period = header & 0x0f;
+        if day > 7 {
+            return Err(invalid(format!("day {} is not 1-7", day)));
+        }
+        if !(1..=12).contains(&period) {
+            return Err(invalid(format!("period {} is not 1-12", period)));
+        }
+        let f = courses
+            .get(course)
+            .ok_or_else(|| invalid(format!("course {} is not in the table", course)))?;
+        o.push(CompactSlot {
+            d: day,
+            s: if header & 0x10 != 0 { "l" } else { "t" }.to_string(),
+            p: period,
+            f: f.clone(),
+        });
+    }
+
+    if reader.pos != body.len() {
+        return Err(ShareCodecError::TrailingBytes {
+            count: body.len() - reader.pos,
+        });
+    }
+    Ok(ShareData {
+        v: SCHEMA_VERSION,
+        u,
+        r,
+        s,
+        h,
+        q,
+        t,
+        o,
+    })
+}
+
+/// Share code plus the `vfriend://` link that opens it
+#[derive(Debug, Clone, Serialize)]
+pub struct ShareLink {
+    pub code: String,
+    pub deep_link: String,
+}
+
+#[tauri::command]
+pub fn encode_share_link(share_data: Value) -> Result<ShareLink, String> {
+    let code = encode_share_data(&migrate_share_data(share_data)?)?;
+    Ok(ShareLink {
+        deep_link: format!("{}{}", SHARE_LINK_PREFIX, code),
+        code,
+    })
+}
+
+#[tauri::command]
+pub fn decode_share_link(code: String) -> Result<ShareData, String> {
+    Ok(decode_share_data(&code)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{share_data, slot};
+
+    fn sample() -> ShareData {
+        let time_table = vec![
+            slot(1, "t", 1, "BCSE101L-TH-AB1-301-ALL"),
+            slot(3, "t", 4, "BCSE101L-TH-AB1-301-ALL"),
+            slot(7, "l", 11, "BCSE101P-LO-AB1-702-ALL"),
+            slot(7, "l", 12, "BCSE101P-LO-AB1-702-ALL"),
+        ];
+        ShareData {
+            r: "23BCE0001".to_string(),
+            h: vec!["chess".to_string(), "रंगोली".to_string()],
+            q: vec!["Ship it".to_string()],
+            t: "2025-06-16T08:00:00Z".to_string(),
+            ..share_data("Sana Iyer", time_table)
+        }
+    }
+
+    fn error(code: &str) -> ShareCodecError {
+        decode_share_data(code).unwrap_err()
+    }
+
+    fn raw(code: &str) -> Vec<u8> {
+        URL_SAFE_NO_PAD.decode(code).unwrap()
+    }
+
+    /// Re-encode bytes with a fresh checksum over everything but the old one
+    fn resealed(mut bytes: Vec<u8>) -> String {
+        bytes.truncate(bytes.len() - CHECKSUM_LEN);
+        let sum = checksum(&bytes);
+        bytes.extend_from_slice(&sum);
+        URL_SAFE_NO_PAD.encode(bytes)
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = sample();
+        let code = encode_share_data(&data).unwrap();
+        let decoded = decode_share_data(&code).unwrap();
+        assert_eq!(decoded.u, data.u);
+        assert_eq!(decoded.r, data.r);
+        assert_eq!(decoded.s, data.s);
+        assert_eq!(decoded.h, data.h);
+        assert_eq!(decoded.q, data.q);
+        assert_eq!(decoded.t, data.t);
+        assert_eq!(decoded.o, data.o);
+
+        let link = format!("  {}{}\n", SHARE_LINK_PREFIX, code);
+        assert_eq!(decode_share_data(&link).unwrap().o, data.o);
+    }
+
+    #[test]
+    fn corruption_is_a_checksum_mismatch() {
+        let code = encode_share_data(&sample()).unwrap();
+        let bytes = raw(&code);
+        for i in 0..bytes.len() {
+            let mut damaged = bytes.clone();
+            damaged[i] ^= 0x01;
+            assert_eq!(
+                error(&URL_SAFE_NO_PAD.encode(damaged)),
+                ShareCodecError::ChecksumMismatch,
+                "flipped byte {}",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn truncation_is_detected() {
+        let bytes = raw(&encode_share_data(&sample()).unwrap());
+        assert_eq!(
+            error(&URL_SAFE_NO_PAD.encode(&bytes[..3])),
+            ShareCodecError::Truncated { field: "checksum" }
+        );
+        for len in 1 + CHECKSUM_LEN..bytes.len() {
+            assert_eq!(
+                error(&URL_SAFE_NO_PAD.encode(&bytes[..len])),
+                ShareCodecError::ChecksumMismatch,
+                "cut at {}",
+                len
+            );
+        }
+        assert_eq!(error(""), ShareCodecError::Empty);
+        assert_eq!(error(SHARE_LINK_PREFIX), ShareCodecError::Empty);
+    }
+
+    #[test]
+    fn fields_cut_short_under_a_valid_checksum_are_truncated() {
+        let mut bytes = raw(&encode_share_data(&sample()).unwrap());
+        // Drop the last slot's course index, keeping the checksum slot
+        bytes.remove(bytes.len() - CHECKSUM_LEN - 1);
+        assert_eq!(
+            error(&resealed(bytes)),
+            ShareCodecError::Truncated { field: "slots" }
+        );
+    }
+
+    #[test]
+    fn wrong_version_is_unsupported() {
+        let mut bytes = raw(&encode_share_data(&sample()).unwrap());
+        bytes[0] = SHARE_CODEC_VERSION + 1;
+        assert_eq!(
+            error(&resealed(bytes.clone())),
+            ShareCodecError::UnsupportedVersion {
+                version: SHARE_CODEC_VERSION + 1
+            }
+        );
+        // Without a matching checksum it is damage, not a newer version
+        assert_eq!(
+            error(&URL_SAFE_NO_PAD.encode(bytes)),
+            ShareCodecError::ChecksumMismatch
+        );
+    }
+
+    #[test]
+    fn semester_too_large_for_u32_is_refused() {
+        let mut bytes = vec![SHARE_CODEC_VERSION, 0, 0];
+        put_varint(&mut bytes, u32::MAX as u64 + 1);
+        bytes.extend_from_slice(&[0; 5]); // h, q, t, courses, slots
+        bytes.extend_from_slice(&[0; CHECKSUM_LEN]);
+        assert_eq!(
+            error(&resealed(bytes)),
+            ShareCodecError::OutOfRange {
+                field: "semester",
+                value: u32::MAX as u64 + 1
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_slots_are_refused() {
+        let mut data = sample();
+        data.o[2].s = "lab".to_string();
+        assert!(matches!(
+            encode_share_data(&data),
+            Err(ShareCodecError::InvalidSlot { index: 2, .. })
+        ));
+        data.o[2].s = "l".to_string();
+        data.o[0].p = 13;
+        assert!(matches!(
+            encode_share_data(&data),
+            Err(ShareCodecError::InvalidSlot { index: 0, .. })
+        ));
+    }
+}
@@ -0,0 +1,221 @@
+//! Weekly load figures for a timetable, for the charts on the profile page.
+//!
+//! Free time is counted inside the teaching day (start of period 1 to the
+//! end of period 12) on days that have classes; days with none are counted
+//! as `days_off` instead, so a free Sunday does not swamp the numbers.
+
+use chrono::NaiveTime;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::location::{day_timeline, Whereabouts};
+use crate::model::{migrate_share_data_list, CompactSlot};
+use crate::newercommands::period_bounds;
+
+/// A first class before this is an early start
+const EARLY_START: (u32, u32) = (8, 30);
+/// A last class ending after this is a late finish
+const LATE_END: (u32, u32) = (17, 30);
+
+fn at(hm: (u32, u32)) -> NaiveTime {
+    NaiveTime::from_hms_opt(hm.0, hm.1, 0).unwrap()
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DayStats {
+    pub day: u8,
+    pub classes: usize, // labs count once per double period
+    pub class_minutes: i64,
+    pub lab_minutes: i64,
+    pub free_minutes: i64,
+    pub gaps: usize, // free stretches between two classes
+    pub longest_streak_minutes: i64,
+    pub first_start: Option<NaiveTime>,
+    pub last_end: Option<NaiveTime>,
+    pub early_start: bool,
+    pub late_end: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WeekStats {
+    pub days: Vec<DayStats>, // Monday to Sunday
+    pub classes: usize,
+    pub class_minutes: i64,
+    pub lab_minutes: i64,
+    pub free_minutes: i64,
+    pub gaps: usize,
+    pub longest_streak_minutes: i64,
+    pub longest_streak_day: Option<u8>,
+    pub busiest_day: Option<u8>,
+    pub days_off: usize,
+    pub early_starts: usize,
+    pub late_ends: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PersonStats {
+    pub name: String,
+    pub registration: String,
+    pub week: WeekStats,
+}
+
+fn lab_minutes(time_table: &[CompactSlot], day: u8) -> i64 {
+    let mut periods: Vec<u8> = time_table
+        .iter()
+        .filter(|slot| slot.d == day && slot.s == "l")
+        .map(|slot| slot.p)
+        .collect();
+    periods.sort_unstable();
+    periods.dedup();
+    periods
+        .into_iter()
+        .filter_map(|p| period_bounds(p, true))
+        .map(|(start, end)| (end - start).num_minutes())
+        .sum()
+}
+
+pub fn day_stats(time_table: &[CompactSlot], day: u8) -> DayStats {
+    let mut stats = DayStats {
+        day,
+        lab_minutes: lab_minutes(time_table, day),
+        ..DayStats::default()
+    };
+    let timeline = day_timeline(time_table, day);
+    let mut streak_start: Option<NaiveTime> = None;
+
+    for (i, interval) in timeline.iter().enumerate() {
+        let minutes = (interval.end - interval.start).num_minutes();
+        match interval.whereabouts {
+            Whereabouts::InClass { .. } => {
+                stats.classes += 1;
+                stats.class_minutes += minutes;
+                stats.first_start.get_or_insert(interval.start);
+                stats.last_end = Some(interval.end);
+                let start = *streak_start.get_or_insert(interval.start);
+                stats.longest_streak_minutes = stats
+                    .longest_streak_minutes
+                    .max((interval.end - start).num_minutes());
+            }
+            // Walking between back-to-back classes keeps the streak going
+            Whereabouts::Transit { .. } => {}
+            Whereabouts::Free { .. } => {
+                streak_start = None;
+                stats.free_minutes += minutes;
+                // The stretch after the last class is not a gap
+                if i + 1 < timeline.len() {
+                    stats.gaps += 1;
+                }
+            }
+        }
+    }
+
+    if let Some(first) = stats.first_start {
+        // Time before the first class is free too
+        let day_start = period_bounds(1, false).map(|(start, _)| start).unwrap();
+        stats.free_minutes += (first - day_start).num_minutes().max(0);
+        stats.early_start = first < at(EARLY_START);
+        stats.late_end = stats.last_end.is_some_and(|end| end > at(LATE_END));
+    }
+    stats
+}
+
+pub fn week_stats(time_table: &[CompactSlot]) -> WeekStats {
+    let days: Vec<DayStats> = (1..=7).map(|day| day_stats(time_table, day)).collect();
+    let mut week = WeekStats::default();
+
+    for day in &days {
+        if day.classes == 0 {
+            week.days_off += 1;
+            continue;
+        }
+        week.classes += day.classes;
+        week.class_minutes += day.class_minutes;
+        week.lab_minutes += day.lab_minutes;
+        week.free_minutes += day.free_minutes;
+        week.gaps += day.gaps;
+        week.early_starts += day.early_start as usize;
+        week.late_ends += day.late_end as usize;
+        if day.longest_streak_minutes > week.longest_streak_minutes {
+            week.longest_streak_minutes = day.longest_streak_minutes;
+            week.longest_streak_day = Some(day.day);
+        }
+    }
+    week.busiest_day = days
+        .iter()
+        .filter(|day| day.classes > 0)
+        .max_by_key(|day| (day.class_minutes, std::cmp::Reverse(day.day)))
+        .map(|day| day.day);
+    week.days = days;
+    week
+}
+
+/// Weekly figures for one timetable
+#[tauri::command]
+pub fn timetable_stats(time_table: Vec<CompactSlot>) -> WeekStats {
+    week_stats(&time_table)
+}
+
+/// Weekly figures for several people at once, e.g. the user and each friend
+#[tauri::command]
+pub fn people_stats(people: Vec<Value>) -> Result<Vec<PersonStats>, String> {
+    Ok(migrate_share_data_list(people)?
+        .into_iter()
+        .map(|person| PersonStats {
+            week: week_stats(&person.o),
+            name: person.u,
+            registration: person.r,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::slot;
+
+    fn monday() -> Vec<CompactSlot> {
+        vec![
+            slot(1, "t", 1, "A1-BCSE101L-SJT-301"),
+            slot(1, "t", 2, "B1-BMAT201L-SJT-301"),
+            slot(1, "t", 5, "E1-BPHY101L-TT-101"),
+        ]
+    }
+
+    #[test]
+    fn day_counts_classes_gaps_and_streaks() {
+        let day = day_stats(&monday(), 1);
+        assert_eq!(day.classes, 3);
+        assert_eq!(day.class_minutes, 150);
+        assert_eq!(day.gaps, 1); // 09:45-11:40; the walk at 08:50 is not one
+        assert_eq!(day.free_minutes, 115 + 415);
+        assert_eq!(day.longest_streak_minutes, 105);
+        assert!(day.early_start && !day.late_end);
+    }
+
+    #[test]
+    fn lab_double_period_is_one_class() {
+        let lab = "L1-BCSE101P-AB1-702";
+        let day = day_stats(&[slot(3, "l", 11, lab), slot(3, "l", 12, lab)], 3);
+        assert_eq!(day.classes, 1);
+        assert_eq!(day.lab_minutes, 100);
+        assert!(day.late_end && !day.early_start);
+    }
+
+    #[test]
+    fn days_without_classes_are_days_off() {
+        let mut time_table = monday();
+        time_table.push(slot(4, "t", 7, "G1-BCHY102L-MB-201"));
+        let week = week_stats(&time_table);
+        assert_eq!(week.days.len(), 7);
+        assert_eq!(week.days_off, 5);
+        assert_eq!(week.classes, 4);
+        assert_eq!(week.busiest_day, Some(1));
+        assert_eq!(week.longest_streak_day, Some(1));
+        assert_eq!(week.early_starts, 1);
+        // A free Sunday does not count as free time
+        assert_eq!(
+            week.free_minutes,
+            530 + day_stats(&time_table, 4).free_minutes
+        );
+    }
+}
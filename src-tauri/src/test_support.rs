@@ -0,0 +1,34 @@
+//! Builders shared by the unit tests, and by `tests/p2p_loopback.rs`
+//! through `#[path]`, which is why everything goes through `crate::model`.
+
+use chrono::NaiveTime;
+
+use crate::model::{CompactSlot, ShareData, SCHEMA_VERSION};
+
+pub(crate) fn slot(d: u8, s: &str, p: u8, f: &str) -> CompactSlot {
+    CompactSlot {
+        d,
+        s: s.to_string(),
+        p,
+        f: f.to_string(),
+    }
+}
+
+/// Current-version share data for a fifth semester student with nothing
+/// but a name and a timetable; override the rest with struct update syntax
+pub(crate) fn share_data(name: &str, time_table: Vec<CompactSlot>) -> ShareData {
+    ShareData {
+        v: SCHEMA_VERSION,
+        u: name.to_string(),
+        r: String::new(),
+        s: 5,
+        h: Vec::new(),
+        q: Vec::new(),
+        t: String::new(),
+        o: time_table,
+    }
+}
+
+pub(crate) fn at(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
@@ -0,0 +1,94 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use iroh::{EndpointAddr, PublicKey, RelayUrl};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+const TICKET_PREFIX: &str = "vft1";
+const DEEP_LINK_PREFIX: &str = "vfriend://connect/";
+
+// ============================================================================
+// Connection Ticket
+// ============================================================================
+
+/// Everything a peer needs to reach us without mDNS: who we are, where we
+/// listen, which relay we are homed on and a one-time invite secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FriendTicket {
+    pub i: String,          // endpoint id
+    pub a: Vec<SocketAddr>, // direct addresses
+    pub r: Option<String>,  // relay url
+    pub k: String,          // invite secret
+}
+
+/// Ticket string plus the deep link used for the QR code
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TicketLink {
+    pub ticket: String,
+    pub deep_link: String,
+}
+
+impl FriendTicket {
+    pub fn new(addr: &EndpointAddr, invite: String) -> Self {
+        Self {
+            i: addr.id.to_string(),
+            a: addr.ip_addrs().copied().collect(),
+            r: addr.relay_urls().next().map(|url| url.to_string()),
+            k: invite,
+        }
+    }
+
+    /// Generate a fresh random invite secret
+    pub fn generate_invite() -> String {
+        URL_SAFE_NO_PAD.encode(rand::random::<[u8; 16]>())
+    }
+
+    /// Rebuild the dialable address of the ticket owner
+    pub fn endpoint_addr(&self) -> Result<EndpointAddr, String> {
+        let id = PublicKey::from_str(&self.i)
+            .map_err(|e| format!("Invalid endpoint ID in ticket: {}", e))?;
+
+        let mut addr = EndpointAddr::new(id);
+        for socket in &self.a {
+            addr = addr.with_ip_addr(*socket);
+        }
+        if let Some(relay) = &self.r {
+            let relay_url = RelayUrl::from_str(relay)
+                .map_err(|e| format!("Invalid relay URL in ticket: {}", e))?;
+            addr = addr.with_relay_url(relay_url);
+        }
+        Ok(addr)
+    }
+
+    pub fn encode(&self) -> Result<String, String> {
+        let json =
+            serde_json::to_vec(self).map_err(|e| format!("Failed to serialize ticket: {}", e))?;
+        Ok(format!("{}{}", TICKET_PREFIX, URL_SAFE_NO_PAD.encode(json)))
+    }
+
+    /// Accepts a bare ticket or a `vfriend://connect/<ticket>` link
+    pub fn decode(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        let input = input.strip_prefix(DEEP_LINK_PREFIX).unwrap_or(input);
+        let body = input
+            .strip_prefix(TICKET_PREFIX)
+            .ok_or("Not a VFriend connection ticket")?;
+
+        let json = URL_SAFE_NO_PAD
+            .decode(body)
+            .map_err(|e| format!("Ticket is not valid base64: {}", e))?;
+        let ticket: FriendTicket =
+            serde_json::from_slice(&json).map_err(|e| format!("Failed to parse ticket: {}", e))?;
+
+        if ticket.k.is_empty() {
+            return Err("Ticket has no invite secret".to_string());
+        }
+        Ok(ticket)
+    }
+
+    pub fn to_link(&self) -> Result<TicketLink, String> {
+        let ticket = self.encode()?;
+        let deep_link = format!("{}{}", DEEP_LINK_PREFIX, ticket);
+        Ok(TicketLink { ticket, deep_link })
+    }
+}
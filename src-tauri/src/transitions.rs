@@ -0,0 +1,180 @@
+use chrono::NaiveTime;
+use serde::Serialize;
+
+use crate::distances::{walk_between, RoomCode};
+use crate::location::{day_timeline, Whereabouts};
+use crate::newercommands::CompactSlot;
+
+/// Less slack than this still counts as a tight change
+const TIGHT_MARGIN_SECONDS: i64 = 120;
+/// Longer gaps are breaks, not changes between classes
+const MAX_GAP_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransitionRisk {
+    Tight,
+    Late,
+    /// The next class starts before this one ends, so no walk makes it
+    Overlap,
+}
+
+/// A change of room between two classes that is hard to make on time
+#[derive(Debug, Clone, Serialize)]
+pub struct Transition {
+    pub day: u8,
+    pub from_course: Option<String>,
+    pub to_course: Option<String>,
+    pub from: RoomCode,
+    pub to: RoomCode,
+    pub leave_at: NaiveTime,
+    pub start_at: NaiveTime,
+    pub gap_seconds: i64,
+    pub walk_seconds: u32,
+    pub late_seconds: i64, // 0 when they should make it
+    pub risk: TransitionRisk,
+}
+
+struct Class {
+    course: Option<String>,
+    room: Option<RoomCode>,
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+/// Back-to-back classes across the week where the walk eats most or all
+/// of the gap
+pub fn tight_transitions(time_table: &[CompactSlot]) -> Vec<Transition> {
+    let mut flagged = Vec::new();
+
+    for day in 1..=7 {
+        let classes: Vec<Class> = day_timeline(time_table, day)
+            .into_iter()
+            .filter_map(|interval| match interval.whereabouts {
+                Whereabouts::InClass { course, room, .. } => Some(Class {
+                    course,
+                    room,
+                    start: interval.start,
+                    end: interval.end,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        for pair in classes.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            let (Some(from), Some(to)) = (&prev.room, &next.room) else {
+                continue;
+            };
+            let gap_seconds = (next.start - prev.end).num_seconds();
+            // Overlapping classes clash even in the same room
+            let overlap = gap_seconds < 0;
+            if (from == to && !overlap) || gap_seconds > MAX_GAP_MINUTES * 60 {
+                continue;
+            }
+            let Ok(walk) = walk_between(from.clone(), to.clone()) else {
+                continue;
+            };
+
+            let margin = gap_seconds - walk.seconds as i64;
+            let risk = if overlap {
+                TransitionRisk::Overlap
+            } else if margin < 0 {
+                TransitionRisk::Late
+            } else if margin < TIGHT_MARGIN_SECONDS {
+                TransitionRisk::Tight
+            } else {
+                continue;
+            };
+
+            flagged.push(Transition {
+                day,
+                from_course: prev.course.clone(),
+                to_course: next.course.clone(),
+                from: from.clone(),
+                to: to.clone(),
+                leave_at: prev.end,
+                start_at: next.start,
+                gap_seconds,
+                walk_seconds: walk.seconds,
+                late_seconds: (-margin).max(0),
+                risk,
+            });
+        }
+    }
+    flagged
+}
+
+#[tauri::command]
+pub fn check_transitions(time_table: Vec<CompactSlot>) -> Vec<Transition> {
+    tight_transitions(&time_table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::slot;
+
+    #[test]
+    fn overlapping_classes_are_an_overlap_not_late() {
+        // Lab period 2 is 08:50-09:40, theory period 2 08:55-09:45
+        let flagged = tight_transitions(&[
+            slot(1, "l", 2, "BCSE101P-LO-AB1-702-ALL"),
+            slot(1, "t", 2, "BMAT201L-TH-SJT-301-ALL"),
+        ]);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].risk, TransitionRisk::Overlap);
+        assert!(flagged[0].gap_seconds < 0);
+    }
+
+    #[test]
+    fn overlap_in_the_same_room_is_still_flagged() {
+        let flagged = tight_transitions(&[
+            slot(1, "l", 2, "BCSE101P-LO-SJT-301-ALL"),
+            slot(1, "t", 2, "BMAT201L-TH-SJT-301-ALL"),
+        ]);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].risk, TransitionRisk::Overlap);
+        assert_eq!(flagged[0].walk_seconds, 0);
+    }
+
+    #[test]
+    fn far_block_in_five_minutes_is_late() {
+        let flagged = tight_transitions(&[
+            slot(1, "t", 1, "BCSE101L-TH-AB1-702-ALL"),
+            slot(1, "t", 2, "BMAT201L-TH-SJT-301-ALL"),
+        ]);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].risk, TransitionRisk::Late);
+        assert_eq!(flagged[0].gap_seconds, 300);
+        assert_eq!(
+            flagged[0].late_seconds,
+            flagged[0].walk_seconds as i64 - 300
+        );
+    }
+
+    #[test]
+    fn stairs_in_the_same_block_are_tight() {
+        let flagged = tight_transitions(&[
+            slot(1, "t", 1, "BCSE101L-TH-SJT-101-ALL"),
+            slot(1, "t", 2, "BMAT201L-TH-SJT-801-ALL"),
+        ]);
+        assert_eq!(flagged.len(), 1);
+        assert_eq!(flagged[0].risk, TransitionRisk::Tight);
+        assert_eq!(flagged[0].late_seconds, 0);
+    }
+
+    #[test]
+    fn same_room_and_long_breaks_are_ignored() {
+        assert!(tight_transitions(&[
+            slot(1, "t", 1, "BCSE101L-TH-SJT-301-ALL"),
+            slot(1, "t", 2, "BMAT201L-TH-SJT-301-ALL"),
+        ])
+        .is_empty());
+        assert!(tight_transitions(&[
+            slot(1, "t", 1, "BCSE101L-TH-AB1-702-ALL"),
+            slot(1, "t", 3, "BMAT201L-TH-SJT-301-ALL"),
+        ])
+        .is_empty());
+    }
+}
@@ -0,0 +1,250 @@
+//! Sanity checks for parsed or received timetables. `build_bitmap` drops
+//! out-of-range periods and `build_kindmap` lets later duplicates win, so
+//! these are reported before a timetable is saved or shared.
+
+use serde::Serialize;
+
+use crate::model::CompactSlot;
+use crate::newercommands::period_bounds;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The slot is dropped or misread by the scheduling commands
+    Error,
+    /// Probably a parsing slip, but the timetable still works
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    DayOutOfRange,
+    PeriodOutOfRange,
+    UnknownKind,
+    Duplicate,
+    Overlap,
+    UnpairedLab,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimetableIssue {
+    pub severity: Severity,
+    pub kind: IssueKind,
+    pub message: String,
+    pub slots: Vec<usize>, // indices into the checked timetable
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValidationReport {
+    pub valid: bool, // no errors; warnings are allowed
+    pub issues: Vec<TimetableIssue>,
+}
+
+fn issue(
+    severity: Severity,
+    kind: IssueKind,
+    slots: Vec<usize>,
+    message: String,
+) -> TimetableIssue {
+    TimetableIssue {
+        severity,
+        kind,
+        message,
+        slots,
+    }
+}
+
+/// Per-slot range and kind checks; returns whether the slot is usable
+fn check_slot(index: usize, slot: &CompactSlot, issues: &mut Vec<TimetableIssue>) -> bool {
+    let mut usable = true;
+    if !(1..=7).contains(&slot.d) {
+        issues.push(issue(
+            Severity::Error,
+            IssueKind::DayOutOfRange,
+            vec![index],
+            format!("{} is on day {}, expected 1-7", slot.f, slot.d),
+        ));
+        usable = false;
+    }
+    if !(1..=12).contains(&slot.p) {
+        issues.push(issue(
+            Severity::Error,
+            IssueKind::PeriodOutOfRange,
+            vec![index],
+            format!("{} is in period {}, expected 1-12", slot.f, slot.p),
+        ));
+        usable = false;
+    }
+    if slot.s != "t" && slot.s != "l" {
+        issues.push(issue(
+            Severity::Error,
+            IssueKind::UnknownKind,
+            vec![index],
+            format!(
+                "{} has kind \"{}\", expected \"t\" or \"l\"",
+                slot.f, slot.s
+            ),
+        ));
+        usable = false;
+    }
+    usable
+}
+
+/// Check a timetable before it is saved or shared
+pub fn validate(time_table: &[CompactSlot]) -> ValidationReport {
+    let mut issues = Vec::new();
+    let usable: Vec<usize> = time_table
+        .iter()
+        .enumerate()
+        .filter(|(i, slot)| check_slot(*i, slot, &mut issues))
+        .map(|(i, _)| i)
+        .collect();
+
+    for (n, &a) in usable.iter().enumerate() {
+        for &b in &usable[n + 1..] {
+            let (x, y) = (&time_table[a], &time_table[b]);
+            if x.d != y.d {
+                continue;
+            }
+            if x == y {
+                issues.push(issue(
+                    Severity::Warning,
+                    IssueKind::Duplicate,
+                    vec![a, b],
+                    format!("{} is listed twice on day {} period {}", x.f, x.d, x.p),
+                ));
+                continue;
+            }
+            let (Some((x_start, x_end)), Some((y_start, y_end))) = (
+                period_bounds(x.p, x.s == "l"),
+                period_bounds(y.p, y.s == "l"),
+            ) else {
+                continue;
+            };
+            if x_start < y_end && y_start < x_end {
+                issues.push(issue(
+                    Severity::Error,
+                    IssueKind::Overlap,
+                    vec![a, b],
+                    format!(
+                        "{} and {} overlap on day {} ({}-{} and {}-{})",
+                        x.f,
+                        y.f,
+                        x.d,
+                        x_start.format("%H:%M"),
+                        x_end.format("%H:%M"),
+                        y_start.format("%H:%M"),
+                        y_end.format("%H:%M")
+                    ),
+                ));
+            }
+        }
+    }
+
+    // Labs run in double periods: 1-2, 3-4, ... 11-12
+    for &i in &usable {
+        let slot = &time_table[i];
+        if slot.s != "l" {
+            continue;
+        }
+        let partner = if slot.p % 2 == 1 {
+            slot.p + 1
+        } else {
+            slot.p - 1
+        };
+        let paired = usable.iter().any(|&j| {
+            let other = &time_table[j];
+            other.d == slot.d && other.p == partner && other.s == "l" && other.f == slot.f
+        });
+        if !paired {
+            issues.push(issue(
+                Severity::Warning,
+                IssueKind::UnpairedLab,
+                vec![i],
+                format!(
+                    "Lab {} on day {} period {} has no matching period {}",
+                    slot.f, slot.d, slot.p, partner
+                ),
+            ));
+        }
+    }
+
+    ValidationReport {
+        valid: !issues.iter().any(|i| i.severity == Severity::Error),
+        issues,
+    }
+}
+
+#[tauri::command]
+pub fn validate_timetable(time_table: Vec<CompactSlot>) -> ValidationReport {
+    validate(&time_table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::slot;
+
+    fn kinds(report: &ValidationReport) -> Vec<IssueKind> {
+        report.issues.iter().map(|i| i.kind).collect()
+    }
+
+    #[test]
+    fn clean_timetable_is_valid() {
+        let report = validate(&[
+            slot(1, "t", 1, "BCSE101L-AB1-301"),
+            slot(1, "l", 3, "BCSE101P-AB1-702"),
+            slot(1, "l", 4, "BCSE101P-AB1-702"),
+        ]);
+        assert!(report.valid);
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn out_of_range_and_unknown_kinds_are_errors() {
+        let report = validate(&[
+            slot(0, "t", 1, "A"),
+            slot(1, "t", 13, "B"),
+            slot(1, "lecture", 2, "C"),
+        ]);
+        assert!(!report.valid);
+        assert_eq!(
+            kinds(&report),
+            vec![
+                IssueKind::DayOutOfRange,
+                IssueKind::PeriodOutOfRange,
+                IssueKind::UnknownKind
+            ]
+        );
+        assert_eq!(report.issues[2].slots, vec![2]);
+    }
+
+    #[test]
+    fn duplicates_warn_and_overlaps_fail() {
+        let duplicate = validate(&[slot(2, "t", 5, "A"), slot(2, "t", 5, "A")]);
+        assert!(duplicate.valid);
+        assert_eq!(kinds(&duplicate), vec![IssueKind::Duplicate]);
+
+        // Lab period 2 (08:50-09:40) runs into theory period 2 (08:55-09:45)
+        let overlap = validate(&[
+            slot(2, "t", 2, "A"),
+            slot(2, "l", 1, "B"),
+            slot(2, "l", 2, "B"),
+        ]);
+        assert!(!overlap.valid);
+        assert_eq!(kinds(&overlap), vec![IssueKind::Overlap]);
+        assert_eq!(overlap.issues[0].slots, vec![0, 2]);
+    }
+
+    #[test]
+    fn lone_lab_period_is_a_warning() {
+        let report = validate(&[slot(3, "l", 7, "B"), slot(3, "l", 9, "B")]);
+        assert!(report.valid);
+        assert_eq!(
+            kinds(&report),
+            vec![IssueKind::UnpairedLab, IssueKind::UnpairedLab]
+        );
+        assert_eq!(report.issues[0].severity, Severity::Warning);
+    }
+}
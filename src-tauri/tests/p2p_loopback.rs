@@ -0,0 +1,529 @@
+//! Friend protocol tests over loopback: every service binds locally with
+//! relays, DNS discovery and mDNS turned off, and peers dial each other
+//! through tickets that carry 127.0.0.1 addresses.
+
+use iroh::{endpoint::Connection, Endpoint, RelayMode, SecretKey};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use vitfriend_lib::forward::{SealedBundle, SYNC_ALPN};
+use vitfriend_lib::model;
+use vitfriend_lib::p2p::{FriendEvent, FriendExchangeService, ServiceOptions, ShareData, ALPN};
+use vitfriend_lib::registry::FriendRecord;
+use vitfriend_lib::ticket::FriendTicket;
+
+// The unit tests' builders; they reach the model through `crate::model`
+#[allow(dead_code)]
+#[path = "../src/test_support.rs"]
+mod test_support;
+
+const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Peer {
+    service: Arc<FriendExchangeService>,
+    events: mpsc::UnboundedReceiver<FriendEvent>,
+    share_data: ShareData,
+}
+
+fn share_data(name: &str, reg: &str) -> ShareData {
+    let time_table = vec![test_support::slot(1, "t", 2, "BCSE101L-AB1-301")];
+    ShareData {
+        r: reg.to_string(),
+        h: vec!["chess".to_string()],
+        q: vec!["hello".to_string()],
+        t: "2025-06-26T15:00:00.000Z".to_string(),
+        ..test_support::share_data(name, time_table)
+    }
+}
+
+async fn spawn_peer(name: &str, reg: &str) -> Peer {
+    spawn_peer_with_key(name, reg, None).await
+}
+
+async fn spawn_peer_with_key(name: &str, reg: &str, secret_key: Option<SecretKey>) -> Peer {
+    let peer = spawn_peer_without_share_data(name, reg, secret_key).await;
+    peer.service.set_share_data(peer.share_data.clone()).await;
+    peer
+}
+
+/// A running peer whose share data is not set yet; `share_data` is what it
+/// would share
+async fn spawn_peer_without_share_data(
+    name: &str,
+    reg: &str,
+    secret_key: Option<SecretKey>,
+) -> Peer {
+    let service = FriendExchangeService::with_options(ServiceOptions {
+        secret_key,
+        local_only: true,
+        disable_mdns: true,
+    })
+    .await
+    .expect("bind service");
+
+    let (tx, events) = mpsc::unbounded_channel();
+    service.start(tx).await.expect("start service");
+
+    Peer {
+        service: Arc::new(service),
+        events,
+        share_data: share_data(name, reg),
+    }
+}
+
+fn loopback(sockets: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    sockets
+        .into_iter()
+        .filter(|addr| addr.is_ipv4())
+        .map(|addr| SocketAddr::new(Ipv4Addr::LOCALHOST.into(), addr.port()))
+        .collect()
+}
+
+/// A ticket whose invite was really issued, so the request is auto-accepted
+async fn invite_ticket(peer: &Peer) -> String {
+    let mut ticket = peer.service.create_ticket().await;
+    ticket.a = loopback(peer.service.bound_sockets());
+    ticket.encode().unwrap()
+}
+
+/// A ticket with a made-up invite, so the request waits for the user
+fn plain_ticket(peer: &Peer) -> String {
+    FriendTicket {
+        i: peer.service.get_endpoint_id(),
+        a: loopback(peer.service.bound_sockets()),
+        r: None,
+        k: FriendTicket::generate_invite(),
+    }
+    .encode()
+    .unwrap()
+}
+
+async fn next_event(peer: &mut Peer) -> FriendEvent {
+    tokio::time::timeout(EVENT_TIMEOUT, peer.events.recv())
+        .await
+        .expect("timed out waiting for event")
+        .expect("event channel closed")
+}
+
+async fn assert_no_event(peer: &mut Peer) {
+    if let Ok(Some(event)) =
+        tokio::time::timeout(Duration::from_millis(500), peer.events.recv()).await
+    {
+        panic!("unexpected event {:?}", event);
+    }
+}
+
+/// `friend` redeems an invite from `peer`, so each has the other's address
+async fn befriend(peer: &mut Peer, friend: &mut Peer) {
+    let ticket = invite_ticket(peer).await;
+    friend
+        .service
+        .send_friend_request_with_ticket(&ticket, friend.share_data.clone())
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_event(peer).await,
+        FriendEvent::RequestAccepted { .. }
+    ));
+    assert!(matches!(
+        next_event(friend).await,
+        FriendEvent::DataReceived { .. }
+    ));
+}
+
+/// Add a friend paired on some earlier run, e.g. restored from a backup.
+/// Nothing tells the endpoint where they are, so it cannot dial them.
+async fn remember_friend(peer: &Peer, endpoint_id: String, name: &str) {
+    let mut records = peer.service.known_friends().await;
+    records.push(FriendRecord {
+        endpoint_id,
+        name: name.to_string(),
+        share_data: None,
+        added_at: 0,
+        bundle_at: 0,
+    });
+    peer.service.set_known_friends(records).await;
+}
+
+async fn wait_for_request(peer: &mut Peer) -> String {
+    match next_event(peer).await {
+        FriendEvent::IncomingRequest { request } => request.remote_id,
+        other => panic!("expected IncomingRequest, got {:?}", other),
+    }
+}
+
+/// Dial a service's friend ALPN from a bare endpoint
+async fn raw_connect(peer: &Peer) -> (Endpoint, Connection) {
+    raw_connect_alpn(peer, ALPN).await
+}
+
+async fn raw_connect_alpn(peer: &Peer, alpn: &[u8]) -> (Endpoint, Connection) {
+    let endpoint = Endpoint::builder()
+        .relay_mode(RelayMode::Disabled)
+        .clear_discovery()
+        .bind()
+        .await
+        .unwrap();
+    let ticket = FriendTicket::decode(&plain_ticket(peer)).unwrap();
+    let conn = endpoint
+        .connect(ticket.endpoint_addr().unwrap(), alpn)
+        .await
+        .unwrap();
+    (endpoint, conn)
+}
+
+/// Send one sync request from a bare endpoint, the way a replaying relay
+/// or a stranger would
+async fn sync_raw(peer: &Peer, request: serde_json::Value) -> serde_json::Value {
+    let (_endpoint, conn) = raw_connect_alpn(peer, SYNC_ALPN).await;
+    let (mut send, mut recv) = conn.open_bi().await.unwrap();
+    send.write_all(&serde_json::to_vec(&request).unwrap())
+        .await
+        .unwrap();
+    send.finish().unwrap();
+    let bytes = recv.read_to_end(100_000).await.unwrap();
+    conn.close(0u32.into(), b"bye");
+    serde_json::from_slice(&bytes).unwrap()
+}
+
+#[tokio::test]
+async fn invite_ticket_is_accepted_automatically() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let mut bob = spawn_peer("Bob", "23BCE0002").await;
+
+    let ticket = invite_ticket(&alice).await;
+    let received = bob
+        .service
+        .send_friend_request_with_ticket(&ticket, bob.share_data.clone())
+        .await
+        .expect("exchange succeeds");
+    assert_eq!(received.r, alice.share_data.r);
+
+    match next_event(&mut alice).await {
+        FriendEvent::RequestAccepted { share_data } => assert_eq!(share_data.r, "23BCE0002"),
+        other => panic!("expected RequestAccepted, got {:?}", other),
+    }
+    match next_event(&mut bob).await {
+        FriendEvent::DataReceived { share_data } => assert_eq!(share_data.u, "Alice"),
+        other => panic!("expected DataReceived, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn invite_can_only_be_used_once() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let bob = spawn_peer("Bob", "23BCE0002").await;
+    let carol = spawn_peer("Carol", "23BCE0003").await;
+
+    let ticket = invite_ticket(&alice).await;
+    bob.service
+        .send_friend_request_with_ticket(&ticket, bob.share_data.clone())
+        .await
+        .unwrap();
+    assert!(matches!(
+        next_event(&mut alice).await,
+        FriendEvent::RequestAccepted { .. }
+    ));
+
+    // The second use falls back to a normal request that needs approval
+    let carol_service = carol.service.clone();
+    let carol_data = carol.share_data.clone();
+    let pending = tokio::spawn(async move {
+        carol_service
+            .send_friend_request_with_ticket(&ticket, carol_data)
+            .await
+    });
+    let remote_id = wait_for_request(&mut alice).await;
+    assert_eq!(remote_id, carol.service.get_endpoint_id());
+
+    alice
+        .service
+        .reject_friend_request(remote_id)
+        .await
+        .unwrap();
+    assert!(pending.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn invite_is_kept_until_there_is_share_data() {
+    let mut alice = spawn_peer_without_share_data("Alice", "23BCE0001", None).await;
+    let bob = spawn_peer("Bob", "23BCE0002").await;
+
+    // Without share data the request waits for the user instead
+    let ticket = invite_ticket(&alice).await;
+    let bob_service = bob.service.clone();
+    let bob_data = bob.share_data.clone();
+    let retry_ticket = ticket.clone();
+    let pending = tokio::spawn(async move {
+        bob_service
+            .send_friend_request_with_ticket(&ticket, bob_data)
+            .await
+    });
+    let remote_id = wait_for_request(&mut alice).await;
+    alice
+        .service
+        .reject_friend_request(remote_id)
+        .await
+        .unwrap();
+    assert!(pending.await.unwrap().is_err());
+
+    // The invite was not spent, so it still works once share data is set
+    alice.service.set_share_data(alice.share_data.clone()).await;
+    let received = bob
+        .service
+        .send_friend_request_with_ticket(&retry_ticket, bob.share_data.clone())
+        .await
+        .expect("exchange succeeds");
+    assert_eq!(received.u, "Alice");
+    assert!(matches!(
+        next_event(&mut alice).await,
+        FriendEvent::RequestAccepted { .. }
+    ));
+}
+
+#[tokio::test]
+async fn manual_accept_exchanges_share_data() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let bob = spawn_peer("Bob", "23BCE0002").await;
+
+    let ticket = plain_ticket(&alice);
+    let bob_service = bob.service.clone();
+    let bob_data = bob.share_data.clone();
+    let pending = tokio::spawn(async move {
+        bob_service
+            .send_friend_request_with_ticket(&ticket, bob_data)
+            .await
+    });
+
+    let remote_id = wait_for_request(&mut alice).await;
+    let from_bob = alice
+        .service
+        .accept_friend_request(remote_id, alice.share_data.clone())
+        .await
+        .expect("accept succeeds");
+    assert_eq!(from_bob.u, "Bob");
+
+    let from_alice = pending.await.unwrap().expect("request succeeds");
+    assert_eq!(from_alice.u, "Alice");
+    assert_eq!(from_alice.o.len(), 1);
+}
+
+#[tokio::test]
+async fn rejected_request_returns_error() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let bob = spawn_peer("Bob", "23BCE0002").await;
+
+    let ticket = plain_ticket(&alice);
+    let bob_service = bob.service.clone();
+    let bob_data = bob.share_data.clone();
+    let pending = tokio::spawn(async move {
+        bob_service
+            .send_friend_request_with_ticket(&ticket, bob_data)
+            .await
+    });
+
+    let remote_id = wait_for_request(&mut alice).await;
+    alice
+        .service
+        .reject_friend_request(remote_id.clone())
+        .await
+        .unwrap();
+
+    let err = pending.await.unwrap().unwrap_err();
+    assert!(err.contains("rejected"), "{}", err);
+
+    // The request is gone once answered
+    assert!(alice
+        .service
+        .accept_friend_request(remote_id, alice.share_data.clone())
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn oversized_request_is_dropped() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let (_endpoint, conn) = raw_connect(&alice).await;
+
+    let (mut send, mut recv) = conn.open_bi().await.unwrap();
+    let name = "x".repeat(5000);
+    let body = format!(r#"{{"from":"23BCE0009","name":"{}"}}"#, name);
+    send.write_all(body.as_bytes()).await.unwrap();
+    send.finish().unwrap();
+
+    assert!(recv.read_to_end(1000).await.is_err());
+    assert_no_event(&mut alice).await;
+}
+
+#[tokio::test]
+async fn oversized_share_data_fails_accept() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let mut bob = spawn_peer("Bob", "23BCE0002").await;
+    bob.share_data.q = vec!["x".repeat(200_000)];
+
+    let ticket = plain_ticket(&alice);
+    let bob_service = bob.service.clone();
+    let bob_data = bob.share_data.clone();
+    let pending = tokio::spawn(async move {
+        bob_service
+            .send_friend_request_with_ticket(&ticket, bob_data)
+            .await
+    });
+
+    let remote_id = wait_for_request(&mut alice).await;
+    let err = alice
+        .service
+        .accept_friend_request(remote_id, alice.share_data.clone())
+        .await
+        .unwrap_err();
+    assert!(err.contains("Failed to read their data"), "{}", err);
+    assert!(pending.await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn malformed_request_is_ignored() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let (_endpoint, conn) = raw_connect(&alice).await;
+
+    let (mut send, mut recv) = conn.open_bi().await.unwrap();
+    send.write_all(b"{ this is not json").await.unwrap();
+    send.finish().unwrap();
+
+    assert!(recv.read_to_end(1000).await.is_err());
+    assert_no_event(&mut alice).await;
+
+    // The service keeps working for well-formed requests
+    let bob = spawn_peer("Bob", "23BCE0002").await;
+    let ticket = invite_ticket(&alice).await;
+    bob.service
+        .send_friend_request_with_ticket(&ticket, bob.share_data.clone())
+        .await
+        .expect("exchange after malformed request");
+}
+
+#[tokio::test]
+async fn disconnect_mid_exchange_fails_accept() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let (endpoint, conn) = raw_connect(&alice).await;
+
+    let (mut send, _recv) = conn.open_bi().await.unwrap();
+    send.write_all(br#"{"from":"23BCE0009","name":"Mallory"}"#)
+        .await
+        .unwrap();
+    send.finish().unwrap();
+
+    let remote_id = wait_for_request(&mut alice).await;
+    assert_eq!(remote_id, endpoint.id().to_string());
+
+    // Hang up before Alice answers
+    conn.close(0u32.into(), b"gone");
+    endpoint.close().await;
+
+    let result = alice
+        .service
+        .accept_friend_request(remote_id, alice.share_data.clone())
+        .await;
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn replayed_bundle_is_refused() {
+    let bob_key = SecretKey::from_bytes(&rand::random());
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let mut bob = spawn_peer_with_key("Bob", "23BCE0002", Some(bob_key.clone())).await;
+    befriend(&mut alice, &mut bob).await;
+
+    // Captured by a relay before Bob's next update
+    let alice_id = alice.service.get_endpoint_id();
+    let mut old_data = bob.share_data.clone();
+    old_data.q = vec!["old".to_string()];
+    let old_bundle = SealedBundle::seal(&bob_key, &alice_id, &old_data).unwrap();
+
+    let mut new_data = bob.share_data.clone();
+    new_data.q = vec!["new".to_string()];
+    bob.service.set_share_data(new_data).await;
+    let report = bob.service.publish_share_update().await.unwrap();
+    assert_eq!(report.delivered, vec![alice_id.clone()]);
+    match next_event(&mut alice).await {
+        FriendEvent::DataReceived { share_data } => assert_eq!(share_data.q, vec!["new"]),
+        other => panic!("expected DataReceived, got {:?}", other),
+    }
+
+    let request = serde_json::json!({ "type": "Deliver", "bundle": old_bundle });
+    let response = sync_raw(&alice, request).await;
+    assert_eq!(response["ok"], false, "{}", response);
+    assert_no_event(&mut alice).await;
+
+    let bob_id = bob.service.get_endpoint_id();
+    let record = alice
+        .service
+        .known_friends()
+        .await
+        .into_iter()
+        .find(|f| f.endpoint_id == bob_id)
+        .expect("Bob is a friend");
+    assert_eq!(record.share_data.unwrap().q, vec!["new"]);
+}
+
+#[tokio::test]
+async fn update_reaches_offline_friend_through_mutual_friend() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let mut bob = spawn_peer("Bob", "23BCE0002").await;
+    let mut mallika = spawn_peer("Mallika", "23BCE0003").await;
+    befriend(&mut mallika, &mut alice).await;
+    befriend(&mut mallika, &mut bob).await;
+
+    // Alice and Bob are friends too, but cannot reach each other directly
+    let alice_id = alice.service.get_endpoint_id();
+    let bob_id = bob.service.get_endpoint_id();
+    remember_friend(&alice, bob_id.clone(), "Bob").await;
+    remember_friend(&bob, alice_id.clone(), "Alice").await;
+
+    let mut new_data = alice.share_data.clone();
+    new_data.q = vec!["new".to_string()];
+    alice.service.set_share_data(new_data).await;
+    let report = alice.service.publish_share_update().await.unwrap();
+    assert_eq!(report.delivered, vec![mallika.service.get_endpoint_id()]);
+    assert_eq!(report.forwarded, vec![bob_id]);
+    assert!(report.failed.is_empty(), "{:?}", report.failed);
+
+    let received = bob.service.fetch_forwarded_updates().await;
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].q, vec!["new"]);
+    match next_event(&mut bob).await {
+        FriendEvent::DataReceived { share_data } => assert_eq!(share_data.u, "Alice"),
+        other => panic!("expected DataReceived, got {:?}", other),
+    }
+
+    // Mallika handed it over, so there is nothing left to fetch
+    assert!(bob.service.fetch_forwarded_updates().await.is_empty());
+}
+
+#[tokio::test]
+async fn bundle_for_non_friend_is_not_carried() {
+    let mut alice = spawn_peer("Alice", "23BCE0001").await;
+    let mut mallika = spawn_peer("Mallika", "23BCE0003").await;
+    befriend(&mut mallika, &mut alice).await;
+
+    // Mallika does not know Carol, so will not hold mail for her
+    let carol_id = SecretKey::from_bytes(&rand::random()).public().to_string();
+    remember_friend(&alice, carol_id.clone(), "Carol").await;
+    let report = alice.service.publish_share_update().await.unwrap();
+    assert_eq!(report.delivered, vec![mallika.service.get_endpoint_id()]);
+    assert!(report.forwarded.is_empty(), "{:?}", report.forwarded);
+    assert_eq!(report.failed, vec![carol_id]);
+
+    // Nor for a friend of hers, when asked by a stranger
+    let stranger = SecretKey::from_bytes(&rand::random());
+    let bundle = SealedBundle::seal(
+        &stranger,
+        &alice.service.get_endpoint_id(),
+        &alice.share_data,
+    )
+    .unwrap();
+    let request = serde_json::json!({ "type": "Store", "bundle": bundle });
+    let response = sync_raw(&mallika, request).await;
+    assert_eq!(response["ok"], false, "{}", response);
+    assert!(alice.service.fetch_forwarded_updates().await.is_empty());
+}